@@ -0,0 +1,94 @@
+use petri_net::*;
+use query::*;
+use query::Operator::*;
+
+//Note: Parts of the net can interact only weakly: if a part only receives tokens from the rest of
+//the net, it can't enable or disable anything in the rest. Queries are therefore checked on the
+//cone of influence of the places they observe, i.e. the places whose tokens can affect them and
+//the transitions that touch those places. Moves outside the cone are stuttering steps.
+//
+//Properties that can't observe stuttering (EF, EU, AG and their boolean combinations) can be
+//checked on the cone alone. Next-state operators and AF/AU/EG can see the other moves (they may
+//fire forever or prevent a deadlock), so such queries are always checked on the full net.
+
+///Places and transitions of a part of the net
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    pub places: Vec<usize>,
+    pub transitions: Vec<usize>,
+}
+
+///Places that can influence the given places and transitions that touch them. Every transition
+///with an arc to or from an influencing place is included and its input places influence it too.
+///The remaining transitions only take tokens from the remaining places, so the markings reachable
+///in the cone are exactly the markings reachable in the net restricted to the cone.
+pub fn cone_of_influence(net: &PetriNet, places: &Vec<usize>) -> Component {
+    let mut touching = vec![Vec::new(); net.initial_marking.len()];
+    for (t, &(ref pre, ref post)) in net.matrix.iter().enumerate() {
+        for &(p, _) in pre.iter().chain(post.iter()) {
+            if touching[p].last() != Some(&t) {
+                touching[p].push(t);
+            }
+        }
+    }
+    let mut in_cone = vec![false; net.initial_marking.len()];
+    let mut included = vec![false; net.matrix.len()];
+    let mut work = places.clone();
+    for &p in places {
+        in_cone[p] = true;
+    }
+    while let Some(p) = work.pop() {
+        for &t in &touching[p] {
+            if included[t] {
+                continue;
+            }
+            included[t] = true;
+            for &(q, _) in &net.matrix[t].0 {
+                if !in_cone[q] {
+                    in_cone[q] = true;
+                    work.push(q);
+                }
+            }
+        }
+    }
+    Component {
+        places: (0..in_cone.len()).filter(|&p| in_cone[p]).collect(),
+        transitions: (0..included.len()).filter(|&t| included[t]).collect(),
+    }
+}
+
+///True if the query can't distinguish stuttering steps of transitions outside the cone
+pub fn is_compositional(query: &Query) -> bool {
+    match query.operator {
+        Atom(_) | Not(_) | And(_) | Or(_) | EF(_) | EU(_, _) | AG(_) => {
+            query.operator.children().into_iter().all(is_compositional)
+        }
+        EX(_) | AX(_) | AF(_) | AU(_, _) | EG(_) => false,
+    }
+}
+
+///Net consisting only of the cone of influence of the places and transitions observed by the query.
+///Returns None if the query has to be checked on the full net, either because of its operators or
+///because the whole net can influence it. The query has to be compiled again against the returned net.
+pub fn restrict(net: &PetriNet, query: &Query) -> Option<PetriNet> {
+    if !is_compositional(query) {
+        return None;
+    }
+    let support = query.support();
+    //observed transitions are only affected by their input places
+    let mut observed: Vec<usize> = support.places.iter().cloned().collect();
+    for &t in &support.transitions {
+        observed.extend(net.matrix[t].0.iter().map(|&(p, _)| p));
+    }
+    observed.sort();
+    observed.dedup();
+    let Component { places, mut transitions } = cone_of_influence(net, &observed);
+    //observed transitions may have no input places in the cone
+    transitions.extend(support.transitions.iter().cloned());
+    transitions.sort();
+    transitions.dedup();
+    if places.len() == net.initial_marking.len() && transitions.len() == net.matrix.len() {
+        return None;
+    }
+    Some(net.subnet(&places, &transitions))
+}
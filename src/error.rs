@@ -0,0 +1,39 @@
+use std::fmt;
+use std::error::Error;
+
+///Problems found while building the net, reading the properties or compiling a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckerError {
+    UnknownArcSource(String),
+    UnknownArcTarget(String),
+    UnknownPlace(String),
+    UnknownTransition(String),
+    UnsupportedFormula(String),
+    InvalidPropertyFile(String),
+}
+
+impl fmt::Display for CheckerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &CheckerError::UnknownArcSource(ref id) => write!(f, "Unknown arc source {}", id),
+            &CheckerError::UnknownArcTarget(ref id) => write!(f, "Unknown arc target {}", id),
+            &CheckerError::UnknownPlace(ref id) => write!(f, "Place not found: {}", id),
+            &CheckerError::UnknownTransition(ref id) => write!(f, "Transition not found: {}", id),
+            &CheckerError::UnsupportedFormula(ref formula) => write!(f, "Unsupported formula {}", formula),
+            &CheckerError::InvalidPropertyFile(ref reason) => write!(f, "Invalid property file {}", reason),
+        }
+    }
+}
+
+impl Error for CheckerError {
+    fn description(&self) -> &str {
+        match self {
+            &CheckerError::UnknownArcSource(_) => "unknown arc source",
+            &CheckerError::UnknownArcTarget(_) => "unknown arc target",
+            &CheckerError::UnknownPlace(_) => "unknown place",
+            &CheckerError::UnknownTransition(_) => "unknown transition",
+            &CheckerError::UnsupportedFormula(_) => "unsupported formula",
+            &CheckerError::InvalidPropertyFile(_) => "invalid property file",
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+
+use ctl;
+use query::Comparison;
+use error::CheckerError;
+
+//Note: Formulas of MCC properties before they are compiled for a specific net, places and
+//transitions are referenced by their ids. The ctl parser only knows a part of the property
+//language (comparisons of place sums and constants, fireability and the CTL operators), its
+//formulas are converted using Formula::from_ctl.
+
+///Integer expression over token counts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integer {
+    Constant(i64),
+    ///Sum of tokens in the places
+    Tokens(Vec<String>),
+    Sum(Vec<Integer>),
+    ///First item minus the rest
+    Difference(Vec<Integer>),
+    Product(Vec<Integer>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Formula {
+    Constant(bool),
+    ///No transition is fireable
+    Deadlock,
+    ///At least one of the transitions is fireable
+    Fireable(Vec<String>),
+    Compare(Integer, Comparison, Integer),
+    Not(Box<Formula>),
+    And(Vec<Formula>),
+    Or(Vec<Formula>),
+    EX(Box<Formula>),
+    AX(Box<Formula>),
+    EF(Box<Formula>),
+    AF(Box<Formula>),
+    EG(Box<Formula>),
+    AG(Box<Formula>),
+    EU(Box<Formula>, Box<Formula>),
+    AU(Box<Formula>, Box<Formula>),
+}
+
+///What a property asks for: the value of a formula in the initial marking, or the maximal
+///number of tokens in the places over all reachable markings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Property {
+    Formula(Formula),
+    PlaceBound(Vec<String>),
+}
+
+impl Formula {
+
+    ///Formula read by the ctl parser
+    pub fn from_ctl(formula: &ctl::Formula) -> Result<Formula, CheckerError> {
+        use ctl::Formula as F;
+        let list = |items: &Vec<F>| items.iter().map(Formula::from_ctl).collect::<Result<Vec<_>, _>>();
+        let boxed = |inner: &F| Formula::from_ctl(inner).map(Box::new);
+        let compare = |left: &ctl::Value, comparison, right: &ctl::Value| {
+            Formula::Compare(integer_from_ctl(left), comparison, integer_from_ctl(right))
+        };
+        Ok(match formula {
+            &F::LT(ref left, ref right) => compare(left, Comparison::LT, right),
+            &F::LE(ref left, ref right) => compare(left, Comparison::LE, right),
+            &F::GT(ref left, ref right) => compare(left, Comparison::GT, right),
+            &F::GE(ref left, ref right) => compare(left, Comparison::GE, right),
+            &F::Fireable(ref transitions) => Formula::Fireable(transitions.clone()),
+            &F::And(ref items) => Formula::And(list(items)?),
+            &F::Or(ref items) => Formula::Or(list(items)?),
+            &F::Not(ref inner) => Formula::Not(boxed(inner)?),
+            &F::EX(ref inner) => Formula::EX(boxed(inner)?),
+            &F::AX(ref inner) => Formula::AX(boxed(inner)?),
+            &F::EF(ref inner) => Formula::EF(boxed(inner)?),
+            &F::AF(ref inner) => Formula::AF(boxed(inner)?),
+            &F::EG(ref inner) => Formula::EG(boxed(inner)?),
+            &F::AG(ref inner) => Formula::AG(boxed(inner)?),
+            &F::EU(ref left, ref right) => Formula::EU(boxed(left)?, boxed(right)?),
+            &F::AU(ref left, ref right) => Formula::AU(boxed(left)?, boxed(right)?),
+            f => return Err(CheckerError::UnsupportedFormula(format!("{}", f))),
+        })
+    }
+}
+
+fn integer_from_ctl(value: &ctl::Value) -> Integer {
+    match value {
+        &ctl::Value::Const(value) => Integer::Constant(value as i64),
+        &ctl::Value::Ref(ref places) => Integer::Tokens(places.clone()),
+    }
+}
+
+///e.g. fireable(t1, t2) or (a && b)
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, name: &str, separator: &str, items: &Vec<T>) -> fmt::Result {
+    write!(f, "{}(", name)?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    write!(f, ")")
+}
+
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Integer::Constant(value) => write!(f, "{}", value),
+            &Integer::Tokens(ref places) => write_list(f, "tokens", ", ", places),
+            &Integer::Sum(ref items) => write_list(f, "", " + ", items),
+            &Integer::Difference(ref items) => write_list(f, "", " - ", items),
+            &Integer::Product(ref items) => write_list(f, "", " * ", items),
+        }
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (operator, inner) = match self {
+            &Formula::Constant(value) => return write!(f, "{}", value),
+            &Formula::Deadlock => return write!(f, "deadlock"),
+            &Formula::Fireable(ref transitions) => return write_list(f, "fireable", ", ", transitions),
+            &Formula::Compare(ref left, comparison, ref right) => return write!(f, "{} {} {}", left, comparison, right),
+            &Formula::And(ref items) => return write_list(f, "", " && ", items),
+            &Formula::Or(ref items) => return write_list(f, "", " || ", items),
+            &Formula::EU(ref left, ref right) => return write!(f, "E({} U {})", left, right),
+            &Formula::AU(ref left, ref right) => return write!(f, "A({} U {})", left, right),
+            &Formula::Not(ref inner) => ("!", inner),
+            &Formula::EX(ref inner) => ("EX", inner),
+            &Formula::AX(ref inner) => ("AX", inner),
+            &Formula::EF(ref inner) => ("EF", inner),
+            &Formula::AF(ref inner) => ("AF", inner),
+            &Formula::EG(ref inner) => ("EG", inner),
+            &Formula::AG(ref inner) => ("AG", inner),
+        };
+        write!(f, "{}({})", operator, inner)
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Property::Formula(ref formula) => write!(f, "{}", formula),
+            &Property::PlaceBound(ref places) => write_list(f, "bound", ", ", places),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use graph::Strategy;
+use storage::MarkingId;
+
+///Small xorshift generator, so that runs with the same seed explore the same markings
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+
+    pub fn new(seed: u64) -> Random {
+        //state can't be zero, mixing also makes similar seeds give different sequences
+        Random { state: (seed ^ 0x9E37_79B9_7F4A_7C15).wrapping_mul(0xBF58_476D_1CE4_E5B9) | 1 }
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    ///Random number from 0 to bound - 1
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next() % (bound as u64)) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut Vec<T>) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+///Markings waiting to be explored by a search that doesn't go depth first
+pub enum Frontier {
+    ///First in, first out
+    Queue(VecDeque<MarkingId>),
+    ///Smallest priority first, newest markings first among equal priorities
+    Heap(BinaryHeap<(Reverse<u32>, MarkingId)>),
+}
+
+impl Frontier {
+
+    pub fn new(strategy: Strategy) -> Frontier {
+        match strategy {
+            Strategy::BestFirst => Frontier::Heap(BinaryHeap::new()),
+            _ => Frontier::Queue(VecDeque::new()),
+        }
+    }
+
+    ///Priority is ignored by the queue
+    pub fn push(&mut self, marking: MarkingId, priority: u32) {
+        match self {
+            &mut Frontier::Queue(ref mut queue) => queue.push_back(marking),
+            &mut Frontier::Heap(ref mut heap) => heap.push((Reverse(priority), marking)),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<MarkingId> {
+        match self {
+            &mut Frontier::Queue(ref mut queue) => queue.pop_front(),
+            &mut Frontier::Heap(ref mut heap) => heap.pop().map(|(_, marking)| marking),
+        }
+    }
+
+    ///True if the search needs priorities of the markings
+    pub fn is_prioritized(&self) -> bool {
+        match self {
+            &Frontier::Heap(_) => true,
+            _ => false,
+        }
+    }
+}
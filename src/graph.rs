@@ -1,48 +1,375 @@
+use std::fmt;
+use std::mem;
+use std::cmp::max;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
 use query::*;
 use query::Operator::*;
 use petri_net::*;
 
 use successors::*;
 use storage::*;
+use sharing::SubqueryTable;
+use frontier::{Frontier, Random};
 use graph::Value::*;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Value { One, Zero, Unknown }
 
+///Final answer for a query, place-bound queries are answered by the bound
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Verdict { True, False, Bound(u64), CannotCompute }
+
+impl From<bool> for Verdict {
+    fn from(value: bool) -> Verdict {
+        if value { Verdict::True } else { Verdict::False }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Verdict::True => write!(f, "true"),
+            &Verdict::False => write!(f, "false"),
+            &Verdict::Bound(value) => write!(f, "{}", value),
+            &Verdict::CannotCompute => write!(f, "unknown"),
+        }
+    }
+}
+
+///Resources a single search is allowed to use. Exceeding any of them gives up the search.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub timeout: Option<Duration>,
+    ///Maximal number of new markings discovered by the search
+    pub max_markings: Option<usize>,
+    ///Maximal (estimated) size of markings and subquery values stored by the search in bytes
+    pub max_memory: Option<usize>,
+}
+
+///Order in which markings are explored when looking for a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    DepthFirst,
+    ///EF, EU and AG explore the closest markings first, so witnesses and counterexamples are
+    ///found in the smallest depth. Other operators are explored depth first.
+    BreadthFirst,
+    ///Depth first search that visits successors in random order given by the seed
+    RandomDepthFirst(u64),
+    ///Before the exhaustive depth first search, EF/EU witnesses and AG counterexamples of the
+    ///checked query are looked for using given number of random walks of bounded length
+    RandomWalk { walks: usize, length: usize, seed: u64 },
+    ///EF over atoms explores markings closest to satisfying the atoms first (distance is given by
+    ///the atoms), other queries are explored depth first
+    BestFirst,
+}
+
+impl Default for Strategy {
+    fn default() -> Strategy {
+        Strategy::DepthFirst
+    }
+}
+
+///Search was interrupted because it ran out of resources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded;
+
+type SearchResult = Result<bool, LimitExceeded>;
+
 pub struct Graph<'a> {
     //first dimension is indexed by queries, second by markings
     assignments: Vec<AssignmentSet>,
-    pub markings: &'a mut MarkingSet<'a>,
+    pub markings: &'a mut MarkingSet,
     pub cache: SuccessorCache,
     pub net: &'a PetriNet,
+    pub limits: Limits,
+    pub strategy: Strategy,
+    ///Present when the current query allows partial order reduction and the search uses
+    ///stubborn sets
+    pub stubborn: Option<StubbornContext>,
+    ///When present, searched queries have to take their ids from this table. Values of subqueries
+    ///are then kept between searches, so subqueries shared by several queries are evaluated once.
+    pub subqueries: Option<SubqueryTable>,
+    ///When set, searches of the top-level temporal operator remember the marking from which every
+    ///marking was explored and the marking that decided the query (see trace::explain)
+    pub record_traces: bool,
+    pub parents: HashMap<MarkingId, MarkingId>,
+    pub trace_end: Option<MarkingId>,
+    ///Buffer for decoding stored markings (see MarkingSet::get_into), whoever decodes into it
+    ///can't expect the contents to survive a call to another part of the search
+    pub decoded: Marking,
+    traced: Option<QueryId>,
+    spill: Option<SpillConfig>,
+    deadline: Option<Instant>,
+    markings_at_start: usize,
+    memory_at_start: usize,
+    steps: usize,
 }
 
 impl <'a> Graph<'a> {
 
-    pub fn new<'b>(net: &'b PetriNet, markings: &'b mut MarkingSet<'b>) -> Graph<'b> {
-        Graph { assignments: vec![], markings: markings, cache: SuccessorCache::new(), net: net }
+    pub fn new<'b>(net: &'b PetriNet, markings: &'b mut MarkingSet) -> Graph<'b> {
+        Graph {
+            assignments: vec![], markings: markings, cache: SuccessorCache::new(), net: net,
+            limits: Limits::default(), strategy: Strategy::default(), stubborn: None, subqueries: None,
+            record_traces: false, parents: HashMap::new(), trace_end: None, decoded: net.initial_marking.clone(), traced: None, spill: None,
+            deadline: None, markings_at_start: 0, memory_at_start: 0, steps: 0,
+        }
     }
 
-    pub fn search<S: Successors>(&mut self, query: &Query) -> bool {
-        self.assignments.clear();
-        self.assignments.resize(query.id + 1, AssignmentSet::new());
+    pub fn search<S: Successors>(&mut self, query: &Query) -> Verdict {
+        if self.subqueries.is_none() {
+            self.assignments.clear();
+        }
+        self.prepare_assignments(query);
+        let mut ids = Vec::new();
+        subquery_ids(query, &mut ids);
+        self.evict_assignments(&ids);
+        self.start_limits();
+        self.stubborn = if S::uses_stubborn_sets() { StubbornContext::new(self.net, query) } else { None };
+        self.parents.clear();
+        self.trace_end = None;
+        self.traced = if self.record_traces { Some(top_operator(query).id) } else { None };
+        if let Some(traced) = self.traced {
+            //the operator has to be explored again even if its value is known from a previous query
+            self.assignments[traced] = self.new_assignment_set();
+        }
         let id = self.markings.insert(&self.net.initial_marking);
-        self.search_inner::<S>(id, query)
+        let result = match self.strategy {
+            Strategy::RandomDepthFirst(_) => self.search_inner::<ShuffledSuccessors<S>>(id, query),
+            Strategy::RandomWalk { walks, length, seed } => {
+                self.random_walks::<S>(id, query, walks, length, seed)
+                    .and_then(|_| self.search_inner::<S>(id, query))
+            }
+            _ => self.search_inner::<S>(id, query),
+        };
+        //limits are only checked now and then, a failed spill file could have been read since
+        let result = result.and_then(|value| if self.io_failed() { Err(LimitExceeded) } else { Ok(value) });
+        match result {
+            Ok(value) => Verdict::from(value),
+            Err(LimitExceeded) => {
+                //interrupted search leaves optimistic guesses behind in the subqueries it evaluated,
+                //values of the other subqueries stay valid
+                for &id in &ids {
+                    self.assignments[id] = self.new_assignment_set();
+                }
+                Verdict::CannotCompute
+            }
+        }
+    }
+
+    ///Evaluate query in arbitrary marking, reusing assignments computed by the last search.
+    ///Limits are not enforced here.
+    pub fn check<S: Successors>(&mut self, marking: MarkingId, query: &Query) -> bool {
+        self.prepare_assignments(query);
+        let limits = mem::replace(&mut self.limits, Limits::default());
+        let deadline = self.deadline.take();
+        let result = self.search_inner::<S>(marking, query);
+        self.limits = limits;
+        self.deadline = deadline;
+        //only a failed spill file can interrupt it, see io_failed
+        result.unwrap_or(false)
+    }
+
+    ///Maximal value of a tokens-count expression over all reachable markings (place-bound). The
+    ///whole state space is explored, so S must not use stubborn sets. Limits are enforced as in
+    ///search.
+    pub fn upper_bound<S: Successors>(&mut self, expression: &Expression) -> Verdict {
+        self.start_limits();
+        self.stubborn = None;
+        let root = self.markings.insert(&self.net.initial_marking);
+        let mut visited = vec![false; root + 1];
+        visited[root] = true;
+        let mut stack = vec![root];
+        let mut cache = self.net.initial_marking.clone();
+        let mut bound = 0;
+        while let Some(id) = stack.pop() {
+            self.markings.get_into(id, &mut self.decoded);
+            bound = max(bound, expression.eval(&self.decoded));
+            let mut succ = S::new();
+            while let Some(next_id) = succ.pop(id, self, &mut cache) {
+                if self.limit_exceeded() {
+                    return Verdict::CannotCompute;
+                }
+                if next_id >= visited.len() {
+                    visited.resize(next_id + 1, false);
+                }
+                if !visited[next_id] {
+                    visited[next_id] = true;
+                    stack.push(next_id);
+                }
+            }
+        }
+        if self.io_failed() { Verdict::CannotCompute } else { Verdict::Bound(bound as u64) }
+    }
+
+    fn start_limits(&mut self) {
+        self.deadline = self.limits.timeout.map(|t| Instant::now() + t);
+        self.markings_at_start = self.markings.len();
+        self.memory_at_start = self.memory();
+        self.steps = 0;
+    }
+
+    ///Move successor cache and assignments to files in the spill directory once they take more
+    ///than the threshold (markings are spilled by MarkingSet::with_spill)
+    pub fn spill_to(&mut self, config: &SpillConfig) {
+        self.cache = SuccessorCache::with_spill(config, self.net.matrix.len());
+        self.assignments.clear();
+        self.spill = Some(config.clone());
+    }
+
+    ///True once a spill file failed, searches are then interrupted and their results can't be trusted
+    pub fn io_failed(&self) -> bool {
+        self.markings.io_failed() || self.cache.io_failed() || self.assignments.iter().any(|a| a.io_failed())
+    }
+
+    ///Memory taken by the stored markings and values of subqueries
+    fn memory(&self) -> usize {
+        self.markings.memory() + self.assignments.iter().map(|a| a.memory()).sum::<usize>()
+    }
+
+    ///Drop values kept from previous searches for subqueries outside of ids once the marking set
+    ///together with the values takes more than its eviction budget
+    fn evict_assignments(&mut self, ids: &Vec<QueryId>) {
+        let budget = match self.markings.budget() {
+            Some(budget) => budget,
+            None => return,
+        };
+        if self.memory() <= budget {
+            return;
+        }
+        for id in 0..self.assignments.len() {
+            if !ids.contains(&id) && self.assignments[id].memory() > 0 {
+                self.assignments[id] = self.new_assignment_set();
+            }
+        }
+    }
+
+    fn new_assignment_set(&self) -> AssignmentSet {
+        match self.spill {
+            Some(ref config) => AssignmentSet::with_spill(config),
+            None => AssignmentSet::new(),
+        }
+    }
+
+    fn prepare_assignments(&mut self, query: &Query) {
+        while self.assignments.len() <= query.id {
+            let set = self.new_assignment_set();
+            self.assignments.push(set);
+        }
+    }
+
+    ///Look for a witness of EF/EU or a counterexample of AG using random walks from the root. If one
+    ///is found, the value of the root is set, otherwise nothing changes.
+    fn random_walks<S: Successors>(
+        &mut self, root_id: MarkingId, query: &Query, walks: usize, length: usize, seed: u64
+    ) -> Result<(), LimitExceeded> {
+        let (reach, path, target) = match query.operator {
+            EF(ref inner) => (&**inner, None, true),
+            EU(ref path, ref reach) => (&**reach, Some(&**path), true),
+            AG(ref inner) => (&**inner, None, false),
+            _ => return Ok(()),
+        };
+        let mut random = Random::new(seed);
+        let mut cache = self.net.initial_marking.clone();
+        for _ in 0..walks {
+            let mut current = root_id;
+            //markings of the walk without loops, used for traces
+            let mut walk = vec![root_id];
+            for _ in 0..(length + 1) {
+                if self.search_inner::<S>(current, reach)? == target {
+                    self.assignments[query.id].set(root_id, if target { One } else { Zero });
+                    if self.traced == Some(query.id) {
+                        for pair in walk.windows(2) {
+                            self.parents.insert(pair[1], pair[0]);
+                        }
+                        self.trace_end = Some(current);
+                    }
+                    return Ok(());
+                }
+                if let Some(path) = path {
+                    if !self.search_inner::<S>(current, path)? {
+                        break;
+                    }
+                }
+                let mut succ = S::new();
+                let mut successors = Vec::new();
+                while let Some(next_id) = succ.pop(current, self, &mut cache) {
+                    if self.limit_exceeded() {
+                        return Err(LimitExceeded);
+                    }
+                    successors.push(next_id);
+                }
+                if successors.is_empty() {
+                    break;
+                }
+                current = successors[random.below(successors.len())];
+                match walk.iter().position(|&m| m == current) {
+                    Some(position) => walk.truncate(position + 1),
+                    None => walk.push(current),
+                }
+            }
+        }
+        Ok(())
     }
 
-    fn search_inner<S: Successors>(&mut self, root_id: MarkingId, query: &Query) -> bool {
+    fn limit_exceeded(&mut self) -> bool {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_markings {
+            if self.markings.len() - self.markings_at_start > max {
+                return true;
+            }
+        }
+        if self.steps % 1024 == 0 {
+            if let Some(max) = self.limits.max_memory {
+                if self.memory().saturating_sub(self.memory_at_start) > max {
+                    return true;
+                }
+            }
+            if let Some(deadline) = self.deadline {
+                if Instant::now() > deadline {
+                    return true;
+                }
+            }
+            if self.io_failed() {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn search_inner<S: Successors>(&mut self, root_id: MarkingId, query: &Query) -> SearchResult {
         let q_id = query.id;
         //Note: This simple cache actually helps A LOT (25% speed-up)
         let mut marking_cache = self.net.initial_marking.clone();
+        macro_rules! check_limits { () => {{
+            if self.limit_exceeded() {
+                return Err(LimitExceeded);
+            }
+        }}}
+        //parent pointers and the deciding marking are only kept for the traced operator
+        let traced = self.traced == Some(q_id);
+        macro_rules! record_parent { ($id:expr, $parent:expr) => {{
+            if traced {
+                self.parents.insert($id, $parent);
+            }
+        }}}
+        macro_rules! record_end { ($id:expr) => {{
+            if traced {
+                self.trace_end = Some($id);
+            }
+        }}}
         macro_rules! next {
             ($inner:ident, $all:expr) => {{
                 let mut succ = S::new();
                 while let Some(next_id) = succ.pop(root_id, self, &mut marking_cache) {
-                    if $all != self.search_inner::<S>(next_id, $inner) {
-                        return !$all;
+                    check_limits![];
+                    if $all != self.search_inner::<S>(next_id, $inner)? {
+                        return Ok(!$all);
                     }
                 }
-                $all
+                Ok($all)
             }};
         }
         macro_rules! exists_path {
@@ -55,7 +382,8 @@ impl <'a> Graph<'a> {
                     //know what escaped the stack and should be therefore reset to Unknown.
                     let mut visited: Vec<MarkingId> = vec![root_id];
                     while let Some((source_id, mut succ)) = stack.pop() {
-                        macro_rules! found_it { () => {{
+                        macro_rules! found_it { ($end:expr) => {{
+                                record_end![$end];
                                 self.assignments[q_id].set(source_id, One);
                                 for &(s,_) in &stack {
                                     self.assignments[q_id].set(s, One)
@@ -65,21 +393,26 @@ impl <'a> Graph<'a> {
                                         self.assignments[q_id].set(*s, Unknown);
                                     }
                                 };
-                                return true;
+                                return Ok(true);
                         }}}
                         if self.assignments[q_id].get(source_id) == Unknown &&
-                            self.search_inner::<S>(source_id, $reach) {
-                            found_it![];
+                            self.search_inner::<S>(source_id, $reach)? {
+                            found_it![source_id];
                         } else {
                             self.assignments[q_id].set(source_id, Zero);
-                            if $until && !self.search_inner::<S>(source_id, $path) {
+                            if $until && !self.search_inner::<S>(source_id, $path)? {
                                 continue;
                             }
                             while let Some(next_id) = succ.pop(source_id, self, &mut marking_cache) {
+                                check_limits![];
                                 match self.assignments[q_id].get(next_id) {
                                     Zero => continue,       //skip!
-                                    One => found_it![],     //found something true from previous run
+                                    One => {                //found something true from previous run
+                                        record_parent![next_id, source_id];
+                                        found_it![next_id];
+                                    }
                                     Unknown => {            //we have to go deeper!
+                                        record_parent![next_id, source_id];
                                         stack.push((source_id, succ));   //repush this config so that we can return to it
                                         stack.push((next_id, S::new()));
                                         visited.push(next_id);
@@ -90,7 +423,7 @@ impl <'a> Graph<'a> {
                         }
                     }
                 }
-                self.assignments[q_id].get(root_id) == One
+                Ok(self.assignments[q_id].get(root_id) == One)
             }}
         }
         macro_rules! all_paths {
@@ -101,19 +434,20 @@ impl <'a> Graph<'a> {
                     let mut stack: Vec<(MarkingId, S)> = vec![(root_id, S::new())];
                     while let Some((source_id, mut succ)) = stack.pop() {
                         if self.assignments[q_id].get(source_id) == Unknown &&
-                            self.search_inner::<S>(source_id, $reach) {
+                            self.search_inner::<S>(source_id, $reach)? {
                             self.assignments[q_id].set(source_id, One);
                         } else {
                             self.assignments[q_id].set(source_id, Zero);
-                            if $until && !self.search_inner::<S>(source_id, $path) {
+                            if $until && !self.search_inner::<S>(source_id, $path)? {
                                 continue;
                             }
                             let mut all_one = true;
                             let mut not_empty = false;
                             while let Some(next_id) = succ.pop(source_id, self, &mut marking_cache) {
+                                check_limits![];
                                 not_empty = true;
                                 match self.assignments[q_id].get(next_id) {
-                                    Zero => return false,   //dead end
+                                    Zero => return Ok(false), //dead end
                                     One => continue,        //found something true from previous run
                                     Unknown => {            //we have to go deeper!
                                         all_one = false;
@@ -130,23 +464,213 @@ impl <'a> Graph<'a> {
                         }
                     }
                 }
-                return self.assignments[q_id].get(root_id) == One;
+                return Ok(self.assignments[q_id].get(root_id) == One);
+            }}
+        }
+        //Breadth first or best first search for EF, EU and AG. Target is the value of reach that
+        //decides the query (true for EF/EU, false for AG). Queued markings get the value the query
+        //has if the target is never found, so that they are not queued twice. Once the target is
+        //found, we only know the value of the root - everything else is reset.
+        macro_rules! frontier_search {
+            ($reach:ident, $until:expr, $path:ident, $target:expr) => {{
+                let (found_value, default_value) = if $target { (One, Zero) } else { (Zero, One) };
+                if self.assignments[q_id].get(root_id) == Unknown {
+                    let mut frontier = Frontier::new(self.strategy);
+                    let mut visited: Vec<MarkingId> = Vec::new();
+                    macro_rules! enqueue { ($id:expr, $parent:expr) => {{
+                        if let Some(parent) = $parent {
+                            record_parent![$id, parent];
+                        }
+                        let priority = if frontier.is_prioritized() {
+                            self.markings.get_into($id, &mut self.decoded);
+                            $reach.distance(&self.decoded).unwrap()
+                        } else { 0 };
+                        self.assignments[q_id].set($id, default_value.clone());
+                        visited.push($id);
+                        frontier.push($id, priority);
+                    }}}
+                    enqueue![root_id, None];
+                    let mut found = false;
+                    'search: while let Some(source_id) = frontier.pop() {
+                        if self.search_inner::<S>(source_id, $reach)? == $target {
+                            record_end![source_id];
+                            found = true;
+                            break;
+                        }
+                        if $until && !self.search_inner::<S>(source_id, $path)? {
+                            continue;
+                        }
+                        let mut succ = S::new();
+                        while let Some(next_id) = succ.pop(source_id, self, &mut marking_cache) {
+                            check_limits![];
+                            let value = self.assignments[q_id].get(next_id);
+                            if value == found_value {   //decided by previous run
+                                record_parent![next_id, source_id];
+                                record_end![next_id];
+                                found = true;
+                                break 'search;
+                            } else if value == Unknown {
+                                enqueue![next_id, Some(source_id)];
+                            }
+                        }
+                    }
+                    if found {
+                        for s in &visited {
+                            self.assignments[q_id].set(*s, Unknown);
+                        }
+                        self.assignments[q_id].set(root_id, found_value);
+                    }   //else: everything reachable was explored, defaults are valid
+                }
+                Ok(self.assignments[q_id].get(root_id) == One)
+            }}
+        }
+        //Greatest fixed point for EG. Configurations on the stack are optimistically marked as One,
+        //so reaching any One configuration means we either closed a lasso or joined a path that
+        //is already known to be valid. Deadlocks satisfying the invariant are valid as well.
+        macro_rules! exists_globally {
+            ($inner:ident) => {{
+                if self.assignments[q_id].get(root_id) == Unknown {
+                    //DFS stack, the flag says whether the configuration has at least one successor
+                    let mut stack: Vec<(MarkingId, S, bool)> = vec![(root_id, S::new(), false)];
+                    while let Some((source_id, mut succ, mut not_empty)) = stack.pop() {
+                        if self.assignments[q_id].get(source_id) == Unknown {
+                            if self.search_inner::<S>(source_id, $inner)? {
+                                self.assignments[q_id].set(source_id, One);
+                            } else {
+                                self.assignments[q_id].set(source_id, Zero);
+                                continue;
+                            }
+                        }
+                        let mut pushed = false;
+                        while let Some(next_id) = succ.pop(source_id, self, &mut marking_cache) {
+                            check_limits![];
+                            not_empty = true;
+                            match self.assignments[q_id].get(next_id) {
+                                Zero => continue,       //skip!
+                                One => return Ok(true), //lasso closed, whole stack is valid
+                                Unknown => {            //we have to go deeper!
+                                    stack.push((source_id, succ, true));
+                                    stack.push((next_id, S::new(), false));
+                                    pushed = true;
+                                    break;
+                                }
+                            }
+                        }
+                        if !pushed {
+                            if not_empty {
+                                self.assignments[q_id].set(source_id, Zero);
+                            } else {
+                                return Ok(true);        //deadlock, maximal path ends here
+                            }
+                        }
+                    }
+                }
+                Ok(self.assignments[q_id].get(root_id) == One)
+            }}
+        }
+        //Greatest fixed point for AG. Dual to exists_path - visited configurations are optimistically
+        //marked as One and once a violation is found, everything that escaped the stack is reset.
+        macro_rules! all_globally {
+            ($inner:ident) => {{
+                if self.assignments[q_id].get(root_id) == Unknown {
+                    let mut stack: Vec<(MarkingId, S)> = vec![(root_id, S::new())];
+                    let mut visited: Vec<MarkingId> = vec![root_id];
+                    while let Some((source_id, mut succ)) = stack.pop() {
+                        macro_rules! violated { ($end:expr) => {{
+                                record_end![$end];
+                                self.assignments[q_id].set(source_id, Zero);
+                                for &(s,_) in &stack {
+                                    self.assignments[q_id].set(s, Zero)
+                                };
+                                for s in &visited {
+                                    if self.assignments[q_id].get(*s) != Zero {
+                                        self.assignments[q_id].set(*s, Unknown);
+                                    }
+                                };
+                                return Ok(false);
+                        }}}
+                        if self.assignments[q_id].get(source_id) == Unknown {
+                            if !self.search_inner::<S>(source_id, $inner)? {
+                                violated![source_id];
+                            }
+                            self.assignments[q_id].set(source_id, One);
+                        }
+                        while let Some(next_id) = succ.pop(source_id, self, &mut marking_cache) {
+                            check_limits![];
+                            match self.assignments[q_id].get(next_id) {
+                                One => continue,        //skip!
+                                Zero => {               //found something false from previous run
+                                    record_parent![next_id, source_id];
+                                    violated![next_id];
+                                }
+                                Unknown => {            //we have to go deeper!
+                                    record_parent![next_id, source_id];
+                                    stack.push((source_id, succ));
+                                    stack.push((next_id, S::new()));
+                                    visited.push(next_id);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(self.assignments[q_id].get(root_id) == One)
             }}
         }
         match query.operator {
-            //TODO Implement EG/AG as maximum fixed point
             //TODO consider caching the EX/AX answers
-            Atom(ref proposition) => proposition(self.markings.get(root_id)),
-            Not(ref inner) => !self.search_inner::<S>(root_id, inner),
-            And(ref items) => items.into_iter().all(|i| self.search_inner::<S>(root_id, i)),
-            Or(ref items) => items.into_iter().any(|i| self.search_inner::<S>(root_id, i)),
+            Atom(ref proposition) => {
+                self.markings.get_into(root_id, &mut self.decoded);
+                Ok(proposition.eval(&self.decoded))
+            }
+            Not(ref inner) => Ok(!self.search_inner::<S>(root_id, inner)?),
+            And(ref items) => {
+                for i in items {
+                    if !self.search_inner::<S>(root_id, i)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Or(ref items) => {
+                for i in items {
+                    if self.search_inner::<S>(root_id, i)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
             EX(ref inner) => next![inner, false],
             AX(ref inner) => next![inner, true],
-            EF(ref inner) => exists_path![inner],
+            EF(ref inner) => match self.strategy {
+                Strategy::BreadthFirst => frontier_search![inner, false, inner, true],
+                Strategy::BestFirst if inner.has_distance() => frontier_search![inner, false, inner, true],
+                _ => exists_path![inner],
+            },
+            EU(ref path, ref reach) if self.strategy == Strategy::BreadthFirst => frontier_search![reach, true, path, true],
             EU(ref path, ref reach) => exists_path![reach, path],
             AF(ref inner) => all_paths![inner],
             AU(ref path, ref reach) => all_paths![reach, path],
+            EG(ref inner) => exists_globally![inner],
+            AG(ref inner) if self.strategy == Strategy::BreadthFirst => frontier_search![inner, false, inner, false],
+            AG(ref inner) => all_globally![inner],
         }
     }
 
 }
+
+///Ids of the query and all of its subqueries
+fn subquery_ids(query: &Query, ids: &mut Vec<QueryId>) {
+    ids.push(query.id);
+    for child in query.operator.children() {
+        subquery_ids(child, ids);
+    }
+}
+
+///Top-level temporal operator (or atom) of the query, i.e. the query without leading negations
+fn top_operator(query: &Query) -> &Query {
+    match query.operator {
+        Not(ref inner) => top_operator(inner),
+        _ => query,
+    }
+}
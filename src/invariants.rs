@@ -0,0 +1,302 @@
+use std::cmp::min;
+
+use petri_net::*;
+use query::*;
+use query::Operator::*;
+
+//Note: Invariants are semi-positive integer vectors y (place invariants, y * C = 0) or x
+//(transition invariants, C * x = 0) where C is the incidence matrix. For a place invariant,
+//the weighted sum of tokens y * m is the same in every reachable marking, which bounds the
+//places in its support. A transition invariant is a multiset of transitions whose firing
+//doesn't change the marking. Both are computed by the Farkas algorithm, which only returns
+//the invariants with minimal support (every other invariant is their non-negative combination).
+
+///Computation is abandoned when the intermediate matrix has more rows
+const MAX_ROWS: usize = 10_000;
+
+///Place invariants of a net with the place bounds they imply. Computing them can take long, so
+///they are computed once per net and shared by all its queries.
+#[derive(Debug, Clone)]
+pub struct PlaceInvariants {
+    pub invariants: Vec<Vec<u64>>,
+    pub bounds: Vec<Option<u32>>,
+}
+
+impl PlaceInvariants {
+
+    ///None if there are too many invariants to compute
+    pub fn compute(net: &PetriNet) -> Option<PlaceInvariants> {
+        place_invariants(net).map(|invariants| {
+            let bounds = place_bounds(net, &invariants);
+            PlaceInvariants { invariants: invariants, bounds: bounds }
+        })
+    }
+}
+
+///Incidence matrix, incidence[p][t] is the change of tokens in p when t fires
+pub fn incidence(net: &PetriNet) -> Vec<Vec<i64>> {
+    let mut incidence = vec![vec![0i64; net.matrix.len()]; net.initial_marking.len()];
+    for (t, &(ref pre, ref post)) in net.matrix.iter().enumerate() {
+        for &(p, w) in pre {
+            incidence[p][t] -= w as i64;
+        }
+        for &(p, w) in post {
+            incidence[p][t] += w as i64;
+        }
+    }
+    incidence
+}
+
+///Minimal support place invariants, None if there are too many to compute
+pub fn place_invariants(net: &PetriNet) -> Option<Vec<Vec<u64>>> {
+    farkas(&incidence(net))
+}
+
+///Minimal support transition invariants, None if there are too many to compute
+pub fn transition_invariants(net: &PetriNet) -> Option<Vec<Vec<u64>>> {
+    let incidence = incidence(net);
+    let transposed = (0..net.matrix.len()).map(|t| incidence.iter().map(|row| row[t]).collect()).collect();
+    farkas(&transposed)
+}
+
+///Semi-positive vectors y with y * matrix = 0 with minimal support
+fn farkas(matrix: &Vec<Vec<i64>>) -> Option<Vec<Vec<u64>>> {
+    let variables = matrix.len();
+    let columns = matrix.first().map(|r| r.len()).unwrap_or(0);
+    //each row is the remaining part of the matrix followed by the combination of variables
+    let mut rows: Vec<Vec<i64>> = matrix.iter().enumerate().map(|(i, row)| {
+        let mut combined = row.clone();
+        combined.extend((0..variables).map(|v| if v == i { 1 } else { 0 }));
+        combined
+    }).collect();
+    for column in 0..columns {
+        let mut next: Vec<Vec<i64>> = rows.iter().filter(|r| r[column] == 0).cloned().collect();
+        for positive in rows.iter().filter(|r| r[column] > 0) {
+            for negative in rows.iter().filter(|r| r[column] < 0) {
+                let (a, b) = (-negative[column], positive[column]);
+                let mut combined = Vec::with_capacity(positive.len());
+                for i in 0..positive.len() {
+                    combined.push(positive[i].checked_mul(a)?.checked_add(negative[i].checked_mul(b)?)?);
+                }
+                let divisor = combined.iter().fold(0, |g, &v| gcd(g, v));
+                if divisor > 1 {
+                    for value in combined.iter_mut() {
+                        *value /= divisor;
+                    }
+                }
+                next.push(combined);
+                if next.len() > MAX_ROWS {
+                    return None;
+                }
+            }
+        }
+        rows = minimal_support(next, columns);
+    }
+    Some(rows.into_iter().map(|r| r[columns..].iter().map(|&v| v as u64).collect()).collect())
+}
+
+///Remove rows whose support (in the variable part) strictly contains the support of another row
+fn minimal_support(rows: Vec<Vec<i64>>, offset: usize) -> Vec<Vec<i64>> {
+    let supports: Vec<Vec<usize>> = rows.iter().map(|r| {
+        (offset..r.len()).filter(|&i| r[i] != 0).collect()
+    }).collect();
+    let contains = |larger: &Vec<usize>, smaller: &Vec<usize>| smaller.iter().all(|i| larger.binary_search(i).is_ok());
+    let mut result = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let redundant = supports.iter().enumerate().any(|(j, other)| {
+            //from duplicate rows only the first one is kept
+            j != i && contains(&supports[i], other) && (other.len() < supports[i].len() || (j < i && rows[j] == *row))
+        });
+        if !redundant {
+            result.push(row.clone());
+        }
+    }
+    result
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+///Maximal number of tokens in each place in reachable markings as implied by the place
+///invariants, None for places not covered by any invariant
+pub fn place_bounds(net: &PetriNet, invariants: &Vec<Vec<u64>>) -> Vec<Option<u32>> {
+    let mut bounds = vec![None; net.initial_marking.len()];
+    for invariant in invariants {
+        let total = weighted_sum(invariant, &net.initial_marking);
+        for (p, &weight) in invariant.iter().enumerate() {
+            if weight > 0 {
+                let bound = min(total / weight, u32::max_value() as u64) as u32;
+                bounds[p] = Some(bounds[p].map_or(bound, |b: u32| min(b, bound)));
+            }
+        }
+    }
+    bounds
+}
+
+fn weighted_sum(invariant: &Vec<u64>, marking: &Marking) -> u64 {
+    invariant.iter().zip(marking.iter()).map(|(&w, &m)| w * m as u64).sum()
+}
+
+///Replace atomic propositions that have the same value in all reachable markings by constants.
+///Returns the number of replaced propositions.
+pub fn simplify(query: &mut Query, net: &PetriNet, invariants: &PlaceInvariants) -> usize {
+    simplify_atoms(query, net, &invariants.invariants, &invariants.bounds)
+}
+
+fn simplify_atoms(query: &mut Query, net: &PetriNet, invariants: &Vec<Vec<u64>>, bounds: &Vec<Option<u32>>) -> usize {
+    match query.operator {
+        Atom(ref mut proposition) => {
+            let mut value = Some(true);
+            for constraint in &proposition.constraints {
+                match decide(constraint, net, invariants, bounds) {
+                    Some(true) => continue,
+                    Some(false) => {
+                        value = Some(false);
+                        break;
+                    }
+                    None => value = None,
+                }
+            }
+            match value {
+                Some(value) => {
+                    *proposition = Proposition::constant(value);
+                    1
+                }
+                None => 0,
+            }
+        }
+        Not(ref mut inner) | EF(ref mut inner) | AF(ref mut inner) | EG(ref mut inner) |
+        AG(ref mut inner) | EX(ref mut inner) | AX(ref mut inner) => {
+            simplify_atoms(inner, net, invariants, bounds)
+        }
+        And(ref mut items) | Or(ref mut items) => {
+            items.iter_mut().map(|item| simplify_atoms(item, net, invariants, bounds)).sum()
+        }
+        AU(ref mut left, ref mut right) | EU(ref mut left, ref mut right) => {
+            simplify_atoms(left, net, invariants, bounds) + simplify_atoms(right, net, invariants, bounds)
+        }
+    }
+}
+
+///Value of the constraint in all reachable markings, if it is always the same
+fn decide(constraint: &Constraint, net: &PetriNet, invariants: &Vec<Vec<u64>>, bounds: &Vec<Option<u32>>) -> Option<bool> {
+    let mut coefficients = vec![0i64; net.initial_marking.len()];
+    for &(p, c) in &constraint.coefficients {
+        coefficients[p] += c;
+    }
+    let support: Vec<usize> = (0..coefficients.len()).filter(|&p| coefficients[p] != 0).collect();
+    if support.is_empty() {
+        return Some(0 >= constraint.bound);
+    }
+    //sum proportional to an invariant is a constant
+    let first = support[0];
+    for invariant in invariants {
+        let same_support = (0..coefficients.len()).all(|p| (coefficients[p] != 0) == (invariant[p] != 0));
+        let proportional = same_support && support.iter().all(|&p| {
+            coefficients[p] as i128 * invariant[first] as i128 == coefficients[first] as i128 * invariant[p] as i128
+        });
+        if proportional {
+            //sum = coefficients[first] / invariant[first] * total
+            let total = weighted_sum(invariant, &net.initial_marking) as i128;
+            return Some(coefficients[first] as i128 * total >= constraint.bound as i128 * invariant[first] as i128);
+        }
+    }
+    //otherwise the sum is somewhere between the bounds of its places
+    let (mut lowest, mut highest) = (Some(0i64), Some(0i64));
+    for &p in &support {
+        let c = coefficients[p];
+        let bound = bounds[p].map(|b| c * b as i64);
+        if c > 0 {
+            highest = highest.and_then(|h| bound.map(|b| h + b));
+        } else {
+            lowest = lowest.and_then(|l| bound.map(|b| l + b));
+        }
+    }
+    if lowest.map_or(false, |l| l >= constraint.bound) {
+        Some(true)
+    } else if highest.map_or(false, |h| h < constraint.bound) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn net(places: usize, initial_marking: Marking, matrix: Vec<(Vec<(usize, u32)>, Vec<(usize, u32)>)>) -> PetriNet {
+        PetriNet {
+            places: (0..places).map(|p| (format!("p{}", p), p)).collect(),
+            transitions: (0..matrix.len()).map(|t| (format!("t{}", t), t)).collect::<HashMap<_, _>>(),
+            initial_marking: initial_marking,
+            matrix: matrix,
+        }
+    }
+
+    ///p0 -> t0 -> p1 -> t1 -> p0
+    fn cycle() -> PetriNet {
+        net(2, vec![2, 0], vec![(vec![(0, 1)], vec![(1, 1)]), (vec![(1, 1)], vec![(0, 1)])])
+    }
+
+    ///Two processes (idle p0/p2, critical p1/p3) sharing a lock p4. Transitions t0/t2 enter and
+    ///t1/t3 leave the critical section.
+    fn mutex() -> PetriNet {
+        net(5, vec![1, 0, 1, 0, 1], vec![
+            (vec![(0, 1), (4, 1)], vec![(1, 1)]),
+            (vec![(1, 1)], vec![(0, 1), (4, 1)]),
+            (vec![(2, 1), (4, 1)], vec![(3, 1)]),
+            (vec![(3, 1)], vec![(2, 1), (4, 1)]),
+        ])
+    }
+
+    fn sorted(mut vectors: Vec<Vec<u64>>) -> Vec<Vec<u64>> {
+        vectors.sort();
+        vectors
+    }
+
+    #[test]
+    fn cycle_invariants() {
+        let net = cycle();
+        assert_eq!(incidence(&net), vec![vec![-1, 1], vec![1, -1]]);
+        assert_eq!(place_invariants(&net), Some(vec![vec![1, 1]]));
+        assert_eq!(transition_invariants(&net), Some(vec![vec![1, 1]]));
+        assert_eq!(place_bounds(&net, &vec![vec![1, 1]]), vec![Some(2), Some(2)]);
+    }
+
+    #[test]
+    fn mutex_invariants() {
+        let net = mutex();
+        assert_eq!(sorted(place_invariants(&net).unwrap()), vec![
+            vec![0, 0, 1, 1, 0],
+            vec![0, 1, 0, 1, 1],
+            vec![1, 1, 0, 0, 0],
+        ]);
+        assert_eq!(sorted(transition_invariants(&net).unwrap()), vec![vec![0, 0, 1, 1], vec![1, 1, 0, 0]]);
+        assert_eq!(PlaceInvariants::compute(&net).unwrap().bounds, vec![Some(1); 5]);
+    }
+
+    #[test]
+    fn net_without_invariants() {
+        //t0 produces tokens in p0 out of nothing
+        let net = net(1, vec![0], vec![(vec![], vec![(0, 1)])]);
+        assert_eq!(place_invariants(&net), Some(vec![]));
+        assert_eq!(transition_invariants(&net), Some(vec![]));
+        assert_eq!(place_bounds(&net, &vec![]), vec![None]);
+    }
+
+    #[test]
+    fn minimal_support_keeps_smallest_rows() {
+        //the first column is the remaining part of the matrix, supports are {1, 2}, {1}, {1} and {2, 3}
+        let rows = vec![vec![0, 1, 1, 0], vec![0, 2, 0, 0], vec![0, 2, 0, 0], vec![0, 0, 1, 3]];
+        assert_eq!(minimal_support(rows, 1), vec![vec![0, 2, 0, 0], vec![0, 0, 1, 3]]);
+    }
+}
@@ -0,0 +1,43 @@
+extern crate pnml;
+extern crate ctl;
+extern crate twox_hash;
+extern crate xml;
+extern crate memmap;
+
+pub mod error;
+pub mod petri_net;
+pub mod query;
+pub mod graph;
+pub mod storage;
+pub mod successors;
+pub mod trace;
+pub mod parallel;
+pub mod reduction;
+pub mod symbolic;
+pub mod decomposition;
+pub mod frontier;
+pub mod simplex;
+pub mod state_equation;
+pub mod invariants;
+pub mod simplification;
+pub mod sharing;
+pub mod properties;
+pub mod formula;
+
+pub use error::CheckerError;
+pub use petri_net::PetriNet;
+pub use query::Query;
+pub use graph::{Graph, Verdict, Limits, Strategy};
+pub use storage::MarkingSet;
+pub use successors::Successors;
+
+use ctl::Formula;
+use successors::CachedSuccessors;
+
+///Check the formula in the initial marking of the net using a fresh state space.
+pub fn check(net: &PetriNet, formula: &Formula) -> Result<Verdict, CheckerError> {
+    let mut markings = MarkingSet::new();
+    let mut graph = Graph::new(net, &mut markings);
+    let query = Query::compile(&formula::Formula::from_ctl(formula)?, net)?;
+    Ok(graph.search::<CachedSuccessors>(&query))
+}
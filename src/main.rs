@@ -1,31 +1,120 @@
-extern crate typed_arena;
 extern crate clap;
 extern crate pnml;
-extern crate ctl;
-extern crate twox_hash;
+extern crate ctl_checker;
 
-mod petri_net;
-mod query;
-mod graph;
-mod storage;
-mod successors;
+mod report;
 
-use ctl::parser::read_formula_list_file;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use pnml::pt_net::parser::read_pt_file;
-use clap::{Arg, App};
-use typed_arena::Arena;
-use query::*;
-use graph::*;
-use petri_net::*;
-use storage::*;
-use successors::OTFSuccessors;
-use successors::CachedSuccessors;
+use clap::{Arg, App, AppSettings, SubCommand};
+use ctl_checker::*;
+use ctl_checker::successors::OTFSuccessors;
+use ctl_checker::successors::CachedSuccessors;
+use ctl_checker::successors::StubbornSuccessors;
+use ctl_checker::trace::explain;
+use ctl_checker::parallel::StateSpace;
+use ctl_checker::symbolic::{SymbolicChecker, Exploration};
+use ctl_checker::reduction::reduce;
+use ctl_checker::decomposition::restrict;
+use ctl_checker::state_equation;
+use ctl_checker::invariants;
+use ctl_checker::invariants::PlaceInvariants;
+use ctl_checker::properties;
+use ctl_checker::formula::{Formula, Integer, Property};
+use ctl_checker::query::Expression;
+use ctl_checker::simplification::simplify;
+use ctl_checker::sharing::SubqueryTable;
+use ctl_checker::storage::SpillConfig;
+use report::{QueryResult, Reporter};
+
+///Command line options shared by all queries
+struct Settings {
+    ///Ids of the queries from the query file
+    ids: Vec<Option<String>>,
+    ///Prefix of ids of queries that don't have one in the query file
+    prefix: String,
+    print_trace: bool,
+    limits: Limits,
+    strategy: Strategy,
+    memory_budget: Option<usize>,
+    ///Directory for spill files and the size of data in memory at which spilling starts
+    spill: Option<SpillConfig>,
+    reduce: bool,
+    decompose: bool,
+    stubborn: bool,
+    state_equation: bool,
+    invariants: bool,
+    ///Place invariants of the model, computed once when invariants are used
+    place_invariants: Option<PlaceInvariants>,
+    verbose: bool,
+}
+
+impl Settings {
+    fn query_id(&self, index: usize) -> String {
+        match self.ids.get(index) {
+            Some(&Some(ref id)) => id.clone(),
+            _ => format!("{}-{}", self.prefix, index),
+        }
+    }
+
+    fn configure(&self, graph: &mut Graph) {
+        graph.limits = self.limits.clone();
+        graph.strategy = self.strategy;
+        graph.record_traces = self.print_trace;
+        if let Some(ref spill) = self.spill {
+            graph.spill_to(spill);
+        }
+    }
+
+    ///Queries of a batch share the values of common subqueries, unless stubborn sets are used
+    ///(the explored part of the graph then depends on the query)
+    fn configure_batch(&self, graph: &mut Graph) {
+        self.configure(graph);
+        if !self.stubborn {
+            graph.subqueries = Some(SubqueryTable::new());
+        }
+    }
+
+    ///Place invariants of a net made from the model for one query (reduced or restricted)
+    fn invariants_of(&self, net: &PetriNet) -> Option<PlaceInvariants> {
+        if self.invariants { PlaceInvariants::compute(net) } else { None }
+    }
+
+    fn marking_set(&self, net: &PetriNet) -> MarkingSet {
+        if let Some(ref spill) = self.spill {
+            match MarkingSet::with_spill(spill) {
+                Ok(set) => return set,
+                Err(error) => {
+                    eprintln!("Cannot create spill file in {}: {}", spill.directory.display(), error);
+                    process::exit(1);
+                }
+            }
+        }
+        match self.memory_budget {
+            Some(budget) => MarkingSet::with_budget(net, budget),
+            None => MarkingSet::new(),
+        }
+    }
+}
 
 fn main() {
     let matches = App::new("Explicit CTL checker")
                         .version("0.1")
                         .author("Samuel Pastva <daemontus@gmail.com>")
                         .about("Verification tool for petri nets.")
+                        .setting(AppSettings::SubcommandsNegateReqs)
+                        .subcommand(SubCommand::with_name("invariants")
+                            .about("Print place and transition invariants of the model and place bounds they imply")
+                            .arg(Arg::with_name("model")
+                                .short("m").long("model")
+                                .value_name("PNML FILE")
+                                .required(true)
+                                .takes_value(true)))
                         .arg(Arg::with_name("model")
                             .short("m").long("model")
                             .value_name("PNML FILE")
@@ -40,24 +129,540 @@ fn main() {
                             .short("n").long("number")
                             .value_name("QUERY NUMBER")
                             .takes_value(true))
+                        .arg(Arg::with_name("trace")
+                            .short("t").long("trace")
+                            .help("Print witness or counterexample firing sequence"))
+                        .arg(Arg::with_name("format")
+                            .short("f").long("format")
+                            .value_name("FORMAT")
+                            .possible_values(&["text", "mcc", "json", "csv"])
+                            .help("Output format of query results")
+                            .takes_value(true))
+                        .arg(Arg::with_name("name")
+                            .long("name")
+                            .value_name("QUERY NAME PREFIX")
+                            .help("Prefix of ids of queries without an id in the query file, query file name by default")
+                            .takes_value(true))
+                        .arg(Arg::with_name("threads")
+                            .long("threads")
+                            .value_name("N")
+                            .help("Explore the whole state space using N threads instead of on-the-fly search")
+                            .conflicts_with_all(&[
+                                "trace", "timeout", "max-markings", "max-memory", "memory-budget", "spill-dir", "jobs",
+                                "strategy", "seed", "walks", "walk-length", "reduce", "decompose", "stubborn",
+                                "state-equation", "invariants"
+                            ])
+                            .takes_value(true))
+                        .arg(Arg::with_name("symbolic")
+                            .long("symbolic")
+                            .help("Compute the state space and all queries symbolically using decision diagrams"))
+                        .arg(Arg::with_name("saturation")
+                            .long("saturation")
+                            .help("Generate the symbolic state space using saturation (implies --symbolic)"))
+                        .arg(Arg::with_name("stats")
+                            .long("stats")
+                            .help("Print statistics of the symbolic state space to stderr (implies --symbolic)"))
+                        .arg(Arg::with_name("strategy")
+                            .long("strategy")
+                            .value_name("STRATEGY")
+                            .possible_values(&["dfs", "bfs", "random", "walk", "best"])
+                            .help("Exploration order of on-the-fly search, best first is guided by the atoms of EF queries")
+                            .takes_value(true))
+                        .arg(Arg::with_name("seed")
+                            .long("seed")
+                            .value_name("N")
+                            .help("Seed of random and walk strategies (0 by default)")
+                            .takes_value(true))
+                        .arg(Arg::with_name("walks")
+                            .long("walks")
+                            .value_name("N")
+                            .help("Number of random walks tried by walk strategy (100 by default)")
+                            .takes_value(true))
+                        .arg(Arg::with_name("walk-length")
+                            .long("walk-length")
+                            .value_name("N")
+                            .help("Maximal number of steps of one random walk (1000 by default)")
+                            .takes_value(true))
+                        .arg(Arg::with_name("timeout")
+                            .long("timeout")
+                            .value_name("SECONDS")
+                            .help("Give up a query after given time (on-the-fly search only)")
+                            .takes_value(true))
+                        .arg(Arg::with_name("max-markings")
+                            .long("max-markings")
+                            .value_name("N")
+                            .help("Give up a query after discovering N new markings (on-the-fly search only)")
+                            .takes_value(true))
+                        .arg(Arg::with_name("max-memory")
+                            .long("max-memory")
+                            .value_name("MB")
+                            .help("Give up a query once markings it discovers and their subquery values take more than given memory (on-the-fly search only)")
+                            .takes_value(true))
+                        .arg(Arg::with_name("memory-budget")
+                            .long("memory-budget")
+                            .value_name("MB")
+                            .help("Evict least visited markings once they take more than given memory and recompute them when needed (values of subqueries kept from previous queries are dropped too)")
+                            .conflicts_with("spill-dir")
+                            .takes_value(true))
+                        .arg(Arg::with_name("spill-dir")
+                            .long("spill-dir")
+                            .value_name("DIRECTORY")
+                            .help("Move markings, cached successors and values of subqueries to memory-mapped files in given directory once they take too much memory")
+                            .takes_value(true))
+                        .arg(Arg::with_name("spill-threshold")
+                            .long("spill-threshold")
+                            .value_name("MB")
+                            .help("Memory taken by markings (and by successors or values of each subquery) at which they are moved to the spill directory (1024 by default)")
+                            .requires("spill-dir")
+                            .takes_value(true))
+                        .arg(Arg::with_name("reduce")
+                            .short("r").long("reduce")
+                            .help("Apply query preserving structural reductions before exploration (on-the-fly search only)"))
+                        .arg(Arg::with_name("decompose")
+                            .short("d").long("decompose")
+                            .help("Check reachability-like queries only on the part of the net that can influence the places they observe (on-the-fly search only)"))
+                        .arg(Arg::with_name("stubborn")
+                            .short("s").long("stubborn")
+                            .help("Use stubborn sets to skip interleavings in reachability queries (on-the-fly search only)"))
+                        .arg(Arg::with_name("state-equation")
+                            .short("e").long("state-equation")
+                            .help("Try to prove reachability queries using the state equation before exploration (on-the-fly search only)"))
+                        .arg(Arg::with_name("invariants")
+                            .short("i").long("invariants")
+                            .help("Replace atomic propositions decided by place invariants with constants (on-the-fly search only)"))
+                        .arg(Arg::with_name("verbose")
+                            .short("v").long("verbose")
+                            .help("Print every checked query (after simplification) with place names and subquery ids"))
+                        .arg(Arg::with_name("jobs")
+                            .short("j").long("jobs")
+                            .value_name("N")
+                            .help("Check up to N queries of a batch concurrently, every worker explores its own state space (markings and successors are not shared)")
+                            .takes_value(true))
                         .get_matches();
+    if let Some(matches) = matches.subcommand_matches("invariants") {
+        let pt_net = read_pt_file(matches.value_of("model").unwrap());
+        match PetriNet::new(&pt_net) {
+            Ok(net) => print_invariants(&net),
+            Err(error) => {
+                eprintln!("Invalid model: {}", error);
+                process::exit(1);
+            }
+        }
+        return;
+    }
     let pt_net = read_pt_file(matches.value_of("model").unwrap());
-    let petri_net = PetriNet::new(&pt_net);
-    let formulas = read_formula_list_file(matches.value_of("queries").unwrap());
+    let petri_net = match PetriNet::new(&pt_net) {
+        Ok(net) => net,
+        Err(error) => {
+            eprintln!("Invalid model: {}", error);
+            process::exit(1);
+        }
+    };
+    let query_file = matches.value_of("queries").unwrap();
+    let (ids, queries): (Vec<_>, Vec<_>) = match properties::read(Path::new(query_file)) {
+        Ok(properties) => properties.into_iter().unzip(),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
     let query_num: isize = matches.value_of("number").unwrap_or("-1").parse().unwrap();
-    let arena = Arena::new();
-    let mut markings = MarkingSet::new(&arena);
+    let prefix = matches.value_of("name").map(|n| n.to_string()).unwrap_or_else(|| {
+        Path::new(query_file).file_stem().and_then(|s| s.to_str()).unwrap_or("query").to_string()
+    });
+    let mut reporter = match report::create(matches.value_of("format").unwrap_or("text")) {
+        Some(reporter) => reporter,
+        None => unreachable!("formats are restricted by clap"),
+    };
+    let seed = matches.value_of("seed").map(|s| s.parse().unwrap()).unwrap_or(0);
+    let settings = Settings {
+        ids: ids,
+        prefix: prefix,
+        print_trace: matches.is_present("trace"),
+        limits: Limits {
+            timeout: matches.value_of("timeout").map(|t| Duration::from_secs(t.parse().unwrap())),
+            max_markings: matches.value_of("max-markings").map(|m| m.parse().unwrap()),
+            max_memory: matches.value_of("max-memory").map(|m| m.parse::<usize>().unwrap() * 1024 * 1024),
+        },
+        strategy: match matches.value_of("strategy").unwrap_or("dfs") {
+            "dfs" => Strategy::DepthFirst,
+            "bfs" => Strategy::BreadthFirst,
+            "random" => Strategy::RandomDepthFirst(seed),
+            "walk" => Strategy::RandomWalk {
+                walks: matches.value_of("walks").map(|w| w.parse().unwrap()).unwrap_or(100),
+                length: matches.value_of("walk-length").map(|l| l.parse().unwrap()).unwrap_or(1000),
+                seed: seed,
+            },
+            "best" => Strategy::BestFirst,
+            _ => unreachable!("strategies are restricted by clap"),
+        },
+        memory_budget: matches.value_of("memory-budget").map(|m| m.parse::<usize>().unwrap() * 1024 * 1024),
+        spill: matches.value_of("spill-dir").map(|d| SpillConfig {
+            directory: PathBuf::from(d),
+            threshold: matches.value_of("spill-threshold").unwrap_or("1024").parse::<usize>().unwrap() * 1024 * 1024,
+        }),
+        reduce: matches.is_present("reduce"),
+        decompose: matches.is_present("decompose"),
+        stubborn: matches.is_present("stubborn"),
+        state_equation: matches.is_present("state-equation"),
+        invariants: matches.is_present("invariants"),
+        place_invariants: if matches.is_present("invariants") { PlaceInvariants::compute(&petri_net) } else { None },
+        verbose: matches.is_present("verbose"),
+    };
+    let selected: Vec<usize> = if query_num >= 0 {
+        vec![query_num as usize]
+    } else {
+        (0..queries.len()).collect()
+    };
+    if matches.is_present("symbolic") || matches.is_present("saturation") || matches.is_present("stats") {
+        let exploration = if matches.is_present("saturation") {
+            Exploration::Saturation
+        } else {
+            Exploration::BreadthFirst
+        };
+        let mut checker = SymbolicChecker::new(&petri_net, exploration);
+        if matches.is_present("stats") {
+            eprintln!("{}", checker.statistics());
+        }
+        for i in selected {
+            let id = settings.query_id(i);
+            match with_property(&queries[i], |property| run_symbolic_query(&mut checker, property, id.clone())) {
+                Ok(result) => reporter.report(&result),
+                Err(error) => reporter.error(&id, &describe(&queries[i]), &error),
+            }
+        }
+        return;
+    }
+    let threads: usize = matches.value_of("threads").unwrap_or("1").parse().unwrap();
+    if threads > 1 {
+        let space = StateSpace::explore(&petri_net, threads);
+        for i in selected {
+            let id = settings.query_id(i);
+            match with_property(&queries[i], |property| run_parallel_query(&space, &petri_net, property, id.clone())) {
+                Ok(result) => reporter.report(&result),
+                Err(error) => reporter.error(&id, &describe(&queries[i]), &error),
+            }
+        }
+        return;
+    }
+    let jobs: usize = matches.value_of("jobs").unwrap_or("1").parse().unwrap();
+    if query_num < 0 && jobs > 1 {
+        run_batch_concurrently(&petri_net, &queries, jobs, &settings, &mut *reporter);
+        return;
+    }
+    let mut markings = settings.marking_set(&petri_net);
     let mut graph = Graph::new(&petri_net, &mut markings);
+    settings.configure(&mut graph);
     if query_num >= 0 {
-        let (query, _) = Query::from_formula(&formulas[query_num as usize], &petri_net, 0);
-        println!("Query: {:?}", formulas[query_num as usize]);
-        println!("Result: {:?}", graph.search::<OTFSuccessors>(&query));
+        let id = settings.query_id(query_num as usize);
+        let property = &queries[query_num as usize];
+        let result = with_property(property, |property| match property {
+            &Property::PlaceBound(ref places) => run_bound::<OTFSuccessors>(&mut graph, places, id.clone()),
+            &Property::Formula(ref formula) if settings.stubborn => {
+                run_formula::<StubbornSuccessors>(&mut graph, formula, id.clone(), &settings)
+            }
+            &Property::Formula(ref formula) => run_formula::<OTFSuccessors>(&mut graph, formula, id.clone(), &settings),
+        });
+        match result {
+            Ok(result) => reporter.report(&result),
+            Err(error) => reporter.error(&id, &describe(property), &error),
+        }
     } else {
         //batch
-        for formula in formulas {
-            let (query, _) = Query::from_formula(&formula, &petri_net, 0);
-            println!("Query: {:?}", formula);
-            println!("Result: {:?}", graph.search::<CachedSuccessors>(&query));
+        settings.configure_batch(&mut graph);
+        for (i, property) in queries.iter().enumerate() {
+            let id = settings.query_id(i);
+            match with_property(property, |property| run_batch_property(&mut graph, property, id.clone(), &settings)) {
+                Ok(result) => reporter.report(&result),
+                Err(error) => reporter.error(&id, &describe(property), &error),
+            }
         }
     }
 }
+
+///Distribute queries of the batch between worker threads. Graph needs exclusive access to its
+///markings, so every worker explores its own state space and keeps it for all queries it checks.
+///Results are reported in the order of the queries, each as soon as all queries before it are done.
+fn run_batch_concurrently(
+    net: &PetriNet, queries: &Vec<Result<Property, CheckerError>>, jobs: usize, settings: &Settings,
+    reporter: &mut Reporter
+) {
+    let next_query = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let next_query = &next_query;
+            scope.spawn(move || {
+                let mut markings = settings.marking_set(net);
+                let mut graph = Graph::new(net, &mut markings);
+                settings.configure_batch(&mut graph);
+                loop {
+                    let i = next_query.fetch_add(1, Ordering::SeqCst);
+                    if i >= queries.len() {
+                        break;
+                    }
+                    let id = settings.query_id(i);
+                    let result = with_property(&queries[i], |property| {
+                        run_batch_property(&mut graph, property, id.clone(), settings)
+                    });
+                    sender.send((i, id, result)).unwrap();
+                }
+            });
+        }
+        drop(sender);
+        let mut finished = (0..queries.len()).map(|_| None).collect::<Vec<_>>();
+        let mut next_report = 0;
+        for (i, id, result) in receiver {
+            finished[i] = Some((id, result));
+            while next_report < finished.len() {
+                match finished[next_report].take() {
+                    Some((_, Ok(result))) => reporter.report(&result),
+                    Some((id, Err(error))) => reporter.error(&id, &describe(&queries[next_report]), &error),
+                    None => break,
+                }
+                next_report += 1;
+            }
+        }
+    });
+}
+
+///Check a property that was read from the property file, or return the error it was read with
+fn with_property<F>(property: &Result<Property, CheckerError>, check: F) -> Result<QueryResult, CheckerError>
+    where F: FnOnce(&Property) -> Result<QueryResult, CheckerError> {
+    match property {
+        &Ok(ref property) => check(property),
+        &Err(ref error) => Err(error.clone()),
+    }
+}
+
+///Formula of the property for error reports, empty if the property couldn't be read
+fn describe(property: &Result<Property, CheckerError>) -> String {
+    property.as_ref().map(|property| format!("{}", property)).unwrap_or(String::new())
+}
+
+///Successors of markings are cached in batch mode, unless stubborn sets are used. Stubborn sets
+///don't keep place bounds, so bounds are always computed with cached successors.
+fn run_batch_property(
+    graph: &mut Graph, property: &Property, id: String, settings: &Settings
+) -> Result<QueryResult, CheckerError> {
+    match property {
+        &Property::PlaceBound(ref places) => run_bound::<CachedSuccessors>(graph, places, id),
+        &Property::Formula(ref formula) if settings.stubborn => {
+            run_formula::<StubbornSuccessors>(graph, formula, id, settings)
+        }
+        &Property::Formula(ref formula) => run_formula::<CachedSuccessors>(graph, formula, id, settings),
+    }
+}
+
+///Place-bound of the places, the whole state space of the graph is explored
+fn run_bound<S: Successors>(
+    graph: &mut Graph, places: &Vec<String>, id: String
+) -> Result<QueryResult, CheckerError> {
+    let start = Instant::now();
+    let expression = Expression::from_integer(&Integer::Tokens(places.clone()), graph.net)?;
+    let verdict = graph.upper_bound::<S>(&expression);
+    Ok(QueryResult {
+        id: id,
+        formula: format!("{}", Property::PlaceBound(places.clone())),
+        verdict: verdict,
+        markings: graph.markings.len(),
+        time: start.elapsed(),
+        techniques: vec!["EXPLICIT", "SEQUENTIAL_PROCESSING"],
+        trace: None,
+    })
+}
+
+///Check one formula, either on the shared graph or on a net reduced specifically for it
+fn run_formula<S: Successors>(
+    graph: &mut Graph, formula: &Formula, id: String, settings: &Settings
+) -> Result<QueryResult, CheckerError> {
+    if settings.state_equation {
+        let start = Instant::now();
+        let query = Query::compile(formula, graph.net)?;
+        if let Some(value) = state_equation::check(graph.net, &query) {
+            return Ok(QueryResult {
+                id: id,
+                formula: format!("{}", formula),
+                verdict: Verdict::from(value),
+                markings: 0,
+                time: start.elapsed(),
+                techniques: vec!["STATE_EQUATIONS", "LINEAR_PROGRAMMING"],
+                trace: None,
+            });
+        }
+    }
+    if settings.decompose {
+        let query = Query::compile(formula, graph.net)?;
+        if let Some(component) = restrict(graph.net, &query) {
+            if settings.verbose {
+                eprintln!("{}: checking {} of {} places", id, component.places.len(), graph.net.places.len());
+            }
+            let mut result = if settings.reduce {
+                run_reduced_query::<S>(&component, formula, id, settings)?
+            } else {
+                let mut markings = settings.marking_set(&component);
+                let mut component_graph = Graph::new(&component, &mut markings);
+                settings.configure(&mut component_graph);
+                let invariants = settings.invariants_of(&component);
+                run_query::<S>(&mut component_graph, formula, id, settings, invariants.as_ref())?
+            };
+            result.techniques.push("TOPOLOGICAL");
+            return Ok(result);
+        }
+    }
+    if settings.reduce {
+        run_reduced_query::<S>(graph.net, formula, id, settings)
+    } else {
+        run_query::<S>(graph, formula, id, settings, settings.place_invariants.as_ref())
+    }
+}
+
+///Reduced nets depend on the query, so each query gets its own state space
+fn run_reduced_query<S: Successors>(
+    net: &PetriNet, formula: &Formula, id: String, settings: &Settings
+) -> Result<QueryResult, CheckerError> {
+    let query = Query::compile(formula, net)?;
+    let reduction = reduce(net, &query);
+    if settings.verbose {
+        eprintln!("{}: removed {} places and {} transitions", id, reduction.removed_places, reduction.removed_transitions);
+    }
+    let mut markings = settings.marking_set(&reduction.net);
+    let mut graph = Graph::new(&reduction.net, &mut markings);
+    settings.configure(&mut graph);
+    let invariants = settings.invariants_of(&reduction.net);
+    let mut result = run_query::<S>(&mut graph, formula, id, settings, invariants.as_ref())?;
+    result.techniques.push("STRUCTURAL_REDUCTION");
+    Ok(result)
+}
+
+///Invariants have to belong to the net of the graph
+fn run_query<S: Successors>(
+    graph: &mut Graph, formula: &Formula, id: String, settings: &Settings, invariants: Option<&PlaceInvariants>
+) -> Result<QueryResult, CheckerError> {
+    let start = Instant::now();
+    let mut query = Query::compile(formula, graph.net)?;
+    if let Some(invariants) = invariants {
+        let simplified = invariants::simplify(&mut query, graph.net, invariants);
+        if settings.verbose {
+            eprintln!("{}: {} atomic propositions decided by invariants", id, simplified);
+        }
+        //decided atoms are constants that can be folded away
+        if simplified > 0 {
+            query = simplify(query);
+        }
+    }
+    if let Some(ref mut subqueries) = graph.subqueries {
+        subqueries.share(&mut query);
+    }
+    if settings.verbose {
+        eprintln!("{}: {}", id, query.pretty(graph.net, true));
+    }
+    let verdict = graph.search::<S>(&query);
+    let time = start.elapsed();
+    let trace = if settings.print_trace && verdict != Verdict::CannotCompute {
+        let trace = explain::<S>(graph, &query);
+        //a trace built from a failed spill file can't be trusted
+        Some(match trace {
+            Some(ref trace) if !graph.io_failed() => format!("{}", trace),
+            _ => "not available".to_string(),
+        })
+    } else {
+        None
+    };
+    let mut techniques = vec!["EXPLICIT", "SEQUENTIAL_PROCESSING"];
+    if settings.stubborn && graph.stubborn.is_some() {
+        techniques.push("STUBBORN_SETS");
+    }
+    Ok(QueryResult {
+        id: id,
+        formula: format!("{}", formula),
+        verdict: verdict,
+        markings: graph.markings.len(),
+        time: time,
+        techniques: techniques,
+        trace: trace,
+    })
+}
+
+fn print_invariants(net: &PetriNet) {
+    let place_names = net.place_names();
+    let transition_names = net.transition_names();
+    //weighted sum of named items, e.g. p1 + 2*p2
+    let sum = |vector: &Vec<u64>, names: &Vec<&str>| {
+        let items: Vec<String> = vector.iter().enumerate().filter(|&(_, &w)| w > 0).map(|(i, &w)| {
+            if w == 1 { names[i].to_string() } else { format!("{}*{}", w, names[i]) }
+        }).collect();
+        items.join(" + ")
+    };
+    match PlaceInvariants::compute(net) {
+        Some(place_invariants) => {
+            println!("Place invariants: {}", place_invariants.invariants.len());
+            for invariant in &place_invariants.invariants {
+                let total: u64 = invariant.iter().zip(net.initial_marking.iter()).map(|(&w, &m)| w * m as u64).sum();
+                println!("  {} = {}", sum(invariant, &place_names), total);
+            }
+            println!("Place bounds:");
+            for (p, &bound) in place_invariants.bounds.iter().enumerate() {
+                match bound {
+                    Some(bound) => println!("  {} <= {}", place_names[p], bound),
+                    None => println!("  {} not covered", place_names[p]),
+                }
+            }
+        }
+        None => println!("Place invariants: too many to compute"),
+    }
+    match invariants::transition_invariants(net) {
+        Some(transition_invariants) => {
+            println!("Transition invariants: {}", transition_invariants.len());
+            for invariant in &transition_invariants {
+                println!("  {}", sum(invariant, &transition_names));
+            }
+        }
+        None => println!("Transition invariants: too many to compute"),
+    }
+}
+
+fn run_parallel_query(
+    space: &StateSpace, net: &PetriNet, property: &Property, id: String
+) -> Result<QueryResult, CheckerError> {
+    let start = Instant::now();
+    let verdict = match property {
+        &Property::Formula(ref formula) => Verdict::from(space.check(&Query::compile(formula, net)?)),
+        &Property::PlaceBound(ref places) => {
+            let expression = Expression::from_integer(&Integer::Tokens(places.clone()), net)?;
+            Verdict::Bound(space.upper_bound(&expression) as u64)
+        }
+    };
+    Ok(QueryResult {
+        id: id,
+        formula: format!("{}", property),
+        verdict: verdict,
+        markings: space.len(),
+        time: start.elapsed(),
+        techniques: vec!["EXPLICIT", "PARALLEL_PROCESSING"],
+        trace: None,
+    })
+}
+
+fn run_symbolic_query(
+    checker: &mut SymbolicChecker, property: &Property, id: String
+) -> Result<QueryResult, CheckerError> {
+    let start = Instant::now();
+    let verdict = match property {
+        &Property::Formula(ref formula) => Verdict::from(checker.check(&Query::compile(formula, checker.net)?)),
+        &Property::PlaceBound(ref places) => {
+            let expression = Expression::from_integer(&Integer::Tokens(places.clone()), checker.net)?;
+            Verdict::Bound(checker.upper_bound(&expression) as u64)
+        }
+    };
+    Ok(QueryResult {
+        id: id,
+        formula: format!("{}", property),
+        verdict: verdict,
+        markings: checker.len(),
+        time: start.elapsed(),
+        techniques: vec!["DECISION_DIAGRAMS", "SEQUENTIAL_PROCESSING"],
+        trace: None,
+    })
+}
@@ -0,0 +1,288 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher, BuildHasher};
+use std::sync::{Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use twox_hash::RandomXxHashBuilder;
+
+use petri_net::*;
+use query::*;
+use query::Operator::*;
+use storage::MarkingId;
+use successors::fire_transition;
+
+//Note: Unlike Graph, the parallel checker is not on-the-fly. The whole state space is explored
+//first (in parallel) and then every subquery is computed as a global fixed point using backward
+//propagation over predecessors (again in parallel). This pays off for queries that need most of
+//the state space anyway and the explored state space can be reused by all queries in a batch.
+
+const SHARDS: usize = 64;
+//how many items does a thread take from the shared queue at once
+const BATCH: usize = 64;
+//how long does local stack have to be so that a thread starts giving work away
+const SHARE_THRESHOLD: usize = 2 * BATCH;
+
+///Marking set that can be accessed by multiple threads at once. Markings are split into shards
+///based on their hash, so that threads rarely wait for the same lock.
+pub struct ConcurrentMarkingSet {
+    hasher: RandomXxHashBuilder,
+    shards: Vec<Mutex<HashMap<Marking, MarkingId, RandomXxHashBuilder>>>,
+    next_id: AtomicUsize,
+}
+
+impl ConcurrentMarkingSet {
+
+    pub fn new() -> ConcurrentMarkingSet {
+        ConcurrentMarkingSet {
+            hasher: Default::default(),
+            shards: (0..SHARDS).map(|_| Mutex::new(Default::default())).collect(),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    ///Returns id of the marking and true if the marking was not present before
+    pub fn insert(&self, marking: &Marking) -> (MarkingId, bool) {
+        let mut hasher = self.hasher.build_hasher();
+        marking.hash(&mut hasher);
+        let mut shard = self.shards[(hasher.finish() as usize) % SHARDS].lock().unwrap();
+        if let Some(&id) = shard.get(marking) {
+            (id, false)
+        } else {
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+            shard.insert(marking.clone(), id);
+            (id, true)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.next_id.load(Ordering::SeqCst)
+    }
+
+    ///Consume the set, returning all markings ordered by id
+    pub fn into_vec(self) -> Vec<Marking> {
+        let mut result = vec![Vec::new(); self.len()];
+        for shard in self.shards {
+            for (marking, id) in shard.into_inner().unwrap() {
+                result[id] = marking;
+            }
+        }
+        result
+    }
+}
+
+struct QueueState<T> {
+    items: Vec<T>,
+    waiting: usize,
+    finished: bool,
+}
+
+///Shared pool of work items. Computation is finished once all threads are waiting for work.
+struct WorkQueue<T> {
+    state: Mutex<QueueState<T>>,
+    ready: Condvar,
+    //approximate number of waiting threads, so that we don't have to lock to find out
+    hungry: AtomicUsize,
+}
+
+impl <T> WorkQueue<T> {
+
+    fn take(&self, threads: usize) -> Option<Vec<T>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if !state.items.is_empty() {
+                let at = state.items.len() - min(state.items.len(), BATCH);
+                return Some(state.items.split_off(at));
+            }
+            if state.finished {
+                return None;
+            }
+            state.waiting += 1;
+            if state.waiting == threads {
+                state.finished = true;
+                self.ready.notify_all();
+                return None;
+            }
+            self.hungry.fetch_add(1, Ordering::SeqCst);
+            state = self.ready.wait(state).unwrap();
+            self.hungry.fetch_sub(1, Ordering::SeqCst);
+            state.waiting -= 1;
+        }
+    }
+
+    fn share(&self, items: Vec<T>) {
+        let mut state = self.state.lock().unwrap();
+        state.items.extend(items);
+        self.ready.notify_all();
+    }
+}
+
+///Process work items on given number of threads. Every thread works on its own DFS stack and
+///gives half of it away when some other thread runs out of work. Each thread also gets its own
+///local state which is returned once everything is done.
+fn work_share<T, L, F>(initial: Vec<T>, threads: usize, process: F) -> Vec<L>
+    where T: Send, L: Default + Send, F: Fn(&mut L, T, &mut Vec<T>) + Sync {
+    let queue = WorkQueue {
+        state: Mutex::new(QueueState { items: initial, waiting: 0, finished: false }),
+        ready: Condvar::new(),
+        hungry: AtomicUsize::new(0),
+    };
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|| {
+            let mut local_state = L::default();
+            let mut stack: Vec<T> = Vec::new();
+            while let Some(batch) = queue.take(threads) {
+                stack.extend(batch);
+                while let Some(item) = stack.pop() {
+                    process(&mut local_state, item, &mut stack);
+                    if stack.len() > SHARE_THRESHOLD && queue.hungry.load(Ordering::Relaxed) > 0 {
+                        let half = stack.len() / 2;
+                        queue.share(stack.split_off(half));
+                    }
+                }
+            }
+            local_state
+        })).collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    })
+}
+
+///Evaluate function for every index in parallel, splitting the range into equal chunks.
+fn par_map<F>(len: usize, threads: usize, f: F) -> Vec<bool> where F: Fn(usize) -> bool + Sync {
+    let chunk = (len + threads - 1) / threads;
+    let f = &f;
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|t| scope.spawn(move || {
+            (min(t * chunk, len)..min((t + 1) * chunk, len)).map(|i| f(i)).collect::<Vec<bool>>()
+        })).collect();
+        workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+    })
+}
+
+///Fully explored state space, the initial marking has id 0
+pub struct StateSpace {
+    pub markings: Vec<Marking>,
+    successors: Vec<Vec<MarkingId>>,
+    //predecessors are stored per edge, so a marking can appear multiple times
+    predecessors: Vec<Vec<MarkingId>>,
+    threads: usize,
+}
+
+impl StateSpace {
+
+    pub fn explore(net: &PetriNet, threads: usize) -> StateSpace {
+        let threads = if threads == 0 { 1 } else { threads };
+        let set = ConcurrentMarkingSet::new();
+        let (initial_id, _) = set.insert(&net.initial_marking);
+        let locals = work_share(vec![(initial_id, net.initial_marking.clone())], threads,
+            |edges: &mut Vec<(MarkingId, Vec<MarkingId>)>, (source_id, source): (MarkingId, Marking), stack| {
+                let mut cache = source.clone();
+                let mut successors = Vec::new();
+                for transition in &net.matrix {
+                    if fire_transition(&mut cache, &source, transition) {
+                        let (id, is_new) = set.insert(&cache);
+                        successors.push(id);
+                        if is_new {
+                            stack.push((id, cache.clone()));
+                        }
+                    }
+                }
+                edges.push((source_id, successors));
+            });
+        let markings = set.into_vec();
+        let mut successors = vec![Vec::new(); markings.len()];
+        let mut predecessors = vec![Vec::new(); markings.len()];
+        for local in locals {
+            for (source, succ) in local {
+                for &target in &succ {
+                    predecessors[target].push(source);
+                }
+                successors[source] = succ;
+            }
+        }
+        StateSpace { markings: markings, successors: successors, predecessors: predecessors, threads: threads }
+    }
+
+    pub fn len(&self) -> usize {
+        self.markings.len()
+    }
+
+    ///Maximal value of the expression over all reachable markings (i.e. place-bound of the
+    ///places in a tokens-count expression)
+    pub fn upper_bound(&self, expression: &Expression) -> i64 {
+        self.markings.iter().map(|m| expression.eval(m)).max().unwrap_or(expression.constant)
+    }
+
+    ///Check the query in the initial marking
+    pub fn check(&self, query: &Query) -> bool {
+        self.evaluate(query)[0]
+    }
+
+    ///Compute the value of the query in every marking of the state space
+    pub fn evaluate(&self, query: &Query) -> Vec<bool> {
+        let n = self.len();
+        match query.operator {
+            Atom(ref proposition) => par_map(n, self.threads, |i| proposition.eval(&self.markings[i])),
+            Not(ref inner) => self.evaluate(inner).into_iter().map(|v| !v).collect(),
+            And(ref items) => items.iter().fold(vec![true; n], |acc, item| {
+                acc.into_iter().zip(self.evaluate(item)).map(|(a, b)| a && b).collect()
+            }),
+            Or(ref items) => items.iter().fold(vec![false; n], |acc, item| {
+                acc.into_iter().zip(self.evaluate(item)).map(|(a, b)| a || b).collect()
+            }),
+            EX(ref inner) => {
+                let inner = self.evaluate(inner);
+                par_map(n, self.threads, |i| self.successors[i].iter().any(|&s| inner[s]))
+            }
+            AX(ref inner) => {
+                let inner = self.evaluate(inner);
+                par_map(n, self.threads, |i| self.successors[i].iter().all(|&s| inner[s]))
+            }
+            EF(ref inner) => self.exists_until(vec![true; n], self.evaluate(inner)),
+            EU(ref path, ref reach) => self.exists_until(self.evaluate(path), self.evaluate(reach)),
+            AF(ref inner) => self.all_until(vec![true; n], self.evaluate(inner)),
+            AU(ref path, ref reach) => self.all_until(self.evaluate(path), self.evaluate(reach)),
+            //greatest fixed points are computed as complements of their duals
+            AG(ref inner) => {
+                let reach = self.evaluate(inner).into_iter().map(|v| !v).collect();
+                self.exists_until(vec![true; n], reach).into_iter().map(|v| !v).collect()
+            }
+            EG(ref inner) => {
+                let reach = self.evaluate(inner).into_iter().map(|v| !v).collect();
+                self.all_until(vec![true; n], reach).into_iter().map(|v| !v).collect()
+            }
+        }
+    }
+
+    ///Backward propagation of reach through predecessors satisfying path
+    fn exists_until(&self, path: Vec<bool>, reach: Vec<bool>) -> Vec<bool> {
+        let result: Vec<AtomicBool> = reach.iter().map(|&v| AtomicBool::new(v)).collect();
+        let initial: Vec<MarkingId> = (0..self.len()).filter(|&i| reach[i]).collect();
+        work_share(initial, self.threads, |_: &mut (), id: MarkingId, stack| {
+            for &p in &self.predecessors[id] {
+                if path[p] && !result[p].swap(true, Ordering::SeqCst) {
+                    stack.push(p);
+                }
+            }
+        });
+        result.into_iter().map(|v| v.into_inner()).collect()
+    }
+
+    ///Backward propagation which counts how many successors of each marking are still
+    ///not known to be valid. Once the counter reaches zero, the marking is valid as well.
+    fn all_until(&self, path: Vec<bool>, reach: Vec<bool>) -> Vec<bool> {
+        let result: Vec<AtomicBool> = reach.iter().map(|&v| AtomicBool::new(v)).collect();
+        let remaining: Vec<AtomicUsize> = self.successors.iter().map(|s| AtomicUsize::new(s.len())).collect();
+        let initial: Vec<MarkingId> = (0..self.len()).filter(|&i| reach[i]).collect();
+        work_share(initial, self.threads, |_: &mut (), id: MarkingId, stack| {
+            for &p in &self.predecessors[id] {
+                if path[p] && remaining[p].fetch_sub(1, Ordering::SeqCst) == 1 &&
+                    !result[p].swap(true, Ordering::SeqCst) {
+                    stack.push(p);
+                }
+            }
+        });
+        result.into_iter().map(|v| v.into_inner()).collect()
+    }
+
+}
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use pnml::pt_net::Net;
 use pnml::pt_net::Element::*;
+use error::CheckerError;
 
 pub type Marking = Vec<u32>;
 
@@ -16,7 +17,7 @@ pub struct PetriNet {
 
 impl PetriNet {
 
-    pub fn new(pt_net: &Net) -> PetriNet {
+    pub fn new(pt_net: &Net) -> Result<PetriNet, CheckerError> {
         let initial_marking = pt_net.elements.iter()
             .filter_map(|e| match e {
                 &Place { ref initial_marking, .. } => Some(initial_marking.clone()), _ => None
@@ -42,28 +43,69 @@ impl PetriNet {
                         if let Some(target_transition) = transitions.get(&*target) {
                             matrix[target_transition.clone()].0.push((source_place.clone(), inscription.clone()));
                         } else {
-                            panic!("Unknown arc target transition {}", target);
+                            return Err(CheckerError::UnknownArcTarget(target.clone()));
                         }
                     } else if let Some(source_transition) = transitions.get(&*source) {
                         //from transtion to place
                         if let Some(target_place) = places.get(&*target) {
                             matrix[source_transition.clone()].1.push((target_place.clone(), inscription.clone()));
                         } else {
-                            panic!("Unknown arg target place {}", target);
+                            return Err(CheckerError::UnknownArcTarget(target.clone()));
                         }
                     } else {
-                        panic!("Unknown arc source {}", source);
+                        return Err(CheckerError::UnknownArcSource(source.clone()));
                     }
                 }
                 _ => {}
             }
         }
 
-        PetriNet {
+        Ok(PetriNet {
             places: places,
             transitions: transitions,
             initial_marking: initial_marking,
             matrix: matrix,
+        })
+    }
+
+    ///Names of all places ordered by their index
+    pub fn place_names(&self) -> Vec<&str> {
+        let mut names = vec!["?"; self.initial_marking.len()];
+        for (name, &index) in &self.places {
+            names[index] = name;
+        }
+        names
+    }
+
+    ///Names of all transitions ordered by their index
+    pub fn transition_names(&self) -> Vec<&str> {
+        let mut names = vec!["?"; self.matrix.len()];
+        for (name, &index) in &self.transitions {
+            names[index] = name;
+        }
+        names
+    }
+
+    ///Net with only the given places and transitions (names are kept, arcs to removed places dropped)
+    pub fn subnet(&self, places: &Vec<usize>, transitions: &Vec<usize>) -> PetriNet {
+        let mut place_index = vec![None; self.initial_marking.len()];
+        for (new, &old) in places.iter().enumerate() {
+            place_index[old] = Some(new);
+        }
+        let mut transition_index = vec![None; self.matrix.len()];
+        for (new, &old) in transitions.iter().enumerate() {
+            transition_index[old] = Some(new);
+        }
+        let remap = |arcs: &Vec<(usize, u32)>| -> Vec<(usize, u32)> {
+            arcs.iter().filter_map(|&(p, w)| place_index[p].map(|p| (p, w))).collect()
+        };
+        PetriNet {
+            places: self.places.iter().filter_map(|(name, &p)| place_index[p].map(|p| (name.clone(), p))).collect(),
+            transitions: self.transitions.iter().filter_map(|(name, &t)| {
+                transition_index[t].map(|t| (name.clone(), t))
+            }).collect(),
+            initial_marking: places.iter().map(|&p| self.initial_marking[p]).collect(),
+            matrix: transitions.iter().map(|&t| (remap(&self.matrix[t].0), remap(&self.matrix[t].1))).collect(),
         }
     }
 
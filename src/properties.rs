@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use error::CheckerError;
+use formula::{Formula, Integer, Property};
+use query::Comparison;
+
+//Note: MCC property files are XML documents with a property-set root, every property has an id,
+//a description and a formula. The formula is either a place-bound or a CTL formula over
+//deadlock, fireability, comparisons of integer expressions and boolean constants. Boolean
+//operators without a counterpart in Formula (implication, equivalence, exclusive disjunction) and
+//comparisons other than <, <=, >=, > are rewritten.
+
+///Properties in the order of the file with their ids (None for properties without an id). A property
+///that can't be read gets its own error, the whole file only fails when it isn't an XML document.
+pub fn read(path: &Path) -> Result<Vec<(Option<String>, Result<Property, CheckerError>)>, CheckerError> {
+    let root = read_document(path)?;
+    Ok(root.children.iter().filter(|p| p.name == "property").map(|property| {
+        let id = property.child("id").map(|id| id.text.trim().to_string());
+        let parsed = property.child("formula").ok_or("missing formula".to_string()).and_then(|formula| {
+            let inner = single(formula)?;
+            match &inner.name[..] {
+                "place-bound" => Ok(Property::PlaceBound(names(inner, "place"))),
+                _ => Ok(Property::Formula(as_formula(inner)?)),
+            }
+        }).map_err(|error| {
+            let id = id.clone().unwrap_or("without id".to_string());
+            CheckerError::InvalidPropertyFile(format!("{}: property {}: {}", path.display(), id, error))
+        });
+        (id, parsed)
+    }).collect())
+}
+
+///XML element, text is the concatenation of all text directly inside the element
+struct Element {
+    name: String,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+
+    ///First child with given name
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+fn read_document(path: &Path) -> Result<Element, CheckerError> {
+    let invalid = |error: String| CheckerError::InvalidPropertyFile(format!("{}: {}", path.display(), error));
+    let file = File::open(path).map_err(|error| invalid(format!("{}", error)))?;
+    parse(BufReader::new(file)).map_err(invalid)
+}
+
+///Element tree of the document, namespaces are dropped from the names
+fn parse<R: Read>(input: R) -> Result<Element, String> {
+    //elements that are not closed yet
+    let mut open: Vec<Element> = Vec::new();
+    for event in EventReader::new(input) {
+        match event.map_err(|error| format!("{}", error))? {
+            XmlEvent::StartElement { name, .. } => {
+                open.push(Element { name: name.local_name, children: Vec::new(), text: String::new() });
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = open.pop().unwrap();
+                match open.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = open.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err("missing root element".to_string())
+}
+
+///The only child of the element
+fn single(element: &Element) -> Result<&Element, String> {
+    match element.children.len() {
+        1 => Ok(&element.children[0]),
+        n => Err(format!("{} has {} children instead of one", element.name, n)),
+    }
+}
+
+///The two children of the element
+fn pair(element: &Element) -> Result<(&Element, &Element), String> {
+    match element.children.len() {
+        2 => Ok((&element.children[0], &element.children[1])),
+        n => Err(format!("{} has {} children instead of two", element.name, n)),
+    }
+}
+
+///Texts of the children with given name (i.e. places of tokens-count)
+fn names(element: &Element, name: &str) -> Vec<String> {
+    element.children.iter().filter(|c| c.name == name).map(|c| c.text.trim().to_string()).collect()
+}
+
+fn as_formula(element: &Element) -> Result<Formula, String> {
+    let items = || element.children.iter().map(as_formula).collect::<Result<Vec<_>, _>>();
+    let pair = || pair(element).and_then(|(a, b)| Ok((as_formula(a)?, as_formula(b)?)));
+    let not = |formula: Formula| Formula::Not(Box::new(formula));
+    Ok(match &element.name[..] {
+        "true" => Formula::Constant(true),
+        "false" => Formula::Constant(false),
+        "deadlock" => Formula::Deadlock,
+        "is-fireable" => Formula::Fireable(names(element, "transition")),
+        "negation" => not(as_formula(single(element)?)?),
+        "conjunction" => Formula::And(items()?),
+        "disjunction" => Formula::Or(items()?),
+        "implication" => {
+            let (a, b) = pair()?;
+            Formula::Or(vec![not(a), b])
+        }
+        "equivalence" => {
+            let (a, b) = pair()?;
+            Formula::Or(vec![Formula::And(vec![a.clone(), b.clone()]), Formula::And(vec![not(a), not(b)])])
+        }
+        "exclusive-disjunction" => {
+            let (a, b) = pair()?;
+            Formula::Or(vec![Formula::And(vec![a.clone(), not(b.clone())]), Formula::And(vec![not(a), b])])
+        }
+        "integer-lt" => compare(element, Comparison::LT)?,
+        "integer-le" => compare(element, Comparison::LE)?,
+        "integer-ge" => compare(element, Comparison::GE)?,
+        "integer-gt" => compare(element, Comparison::GT)?,
+        "integer-eq" => Formula::And(vec![compare(element, Comparison::LE)?, compare(element, Comparison::GE)?]),
+        "integer-ne" => Formula::Or(vec![compare(element, Comparison::LT)?, compare(element, Comparison::GT)?]),
+        "exists-path" => path(single(element)?, true)?,
+        "all-paths" => path(single(element)?, false)?,
+        name => return Err(format!("unknown element {}", name)),
+    })
+}
+
+///Temporal operator under a path quantifier
+fn path(operator: &Element, exists: bool) -> Result<Formula, String> {
+    let inner = || single(operator).and_then(as_formula).map(Box::new);
+    Ok(match (&operator.name[..], exists) {
+        ("next", true) => Formula::EX(inner()?),
+        ("next", false) => Formula::AX(inner()?),
+        ("finally", true) => Formula::EF(inner()?),
+        ("finally", false) => Formula::AF(inner()?),
+        ("globally", true) => Formula::EG(inner()?),
+        ("globally", false) => Formula::AG(inner()?),
+        ("until", _) => {
+            let side = |name: &str| match operator.child(name) {
+                Some(side) => single(side).and_then(as_formula).map(Box::new),
+                None => Err(format!("until without {}", name)),
+            };
+            if exists {
+                Formula::EU(side("before")?, side("reach")?)
+            } else {
+                Formula::AU(side("before")?, side("reach")?)
+            }
+        }
+        (name, _) => return Err(format!("unknown path operator {}", name)),
+    })
+}
+
+fn compare(element: &Element, comparison: Comparison) -> Result<Formula, String> {
+    let (left, right) = pair(element)?;
+    Ok(Formula::Compare(as_integer(left)?, comparison, as_integer(right)?))
+}
+
+fn as_integer(element: &Element) -> Result<Integer, String> {
+    let items = || element.children.iter().map(as_integer).collect::<Result<Vec<_>, _>>();
+    Ok(match &element.name[..] {
+        "integer-constant" => match element.text.trim().parse() {
+            Ok(value) => Integer::Constant(value),
+            Err(_) => return Err(format!("invalid integer constant {}", element.text.trim())),
+        },
+        "tokens-count" => Integer::Tokens(names(element, "place")),
+        "integer-sum" => Integer::Sum(items()?),
+        "integer-difference" => Integer::Difference(items()?),
+        "integer-product" => Integer::Product(items()?),
+        name => return Err(format!("unknown integer expression {}", name)),
+    })
+}
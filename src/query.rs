@@ -1,15 +1,279 @@
 use petri_net::*;
-use ctl::*;
-use ctl::Formula::*;
-use ctl::Value::*;
+use formula::{Formula, Integer};
+use formula::Formula::*;
 use std::fmt;
+use std::cmp::min;
+use std::collections::HashSet;
 use query::Operator as Op;
-
-//TODO: don't use closures for propositions, they are slow (maybe wait with this for direct intercompilation)
-type Evaluable = Box<Fn(&Marking) -> u32>;
+use error::CheckerError;
+use simplification::simplify;
 
 pub type QueryId = usize;
-pub type Proposition = Box<Fn(&Marking) -> bool>;
+
+///Sum of tokens in places times their coefficients plus a constant
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Expression {
+    pub coefficients: Vec<(usize, i64)>,
+    pub constant: i64,
+}
+
+impl Expression {
+
+    pub fn constant(value: i64) -> Expression {
+        Expression { coefficients: vec![], constant: value }
+    }
+
+    ///Sum of tokens in given places (i.e. tokens-count)
+    pub fn tokens(places: &Vec<usize>) -> Expression {
+        Expression { coefficients: places.iter().map(|&p| (p, 1)).collect(), constant: 0 }
+    }
+
+    pub fn add(&self, other: &Expression) -> Expression {
+        let mut coefficients = self.coefficients.clone();
+        coefficients.extend(other.coefficients.iter().cloned());
+        Expression { coefficients: coefficients, constant: self.constant + other.constant }
+    }
+
+    pub fn subtract(&self, other: &Expression) -> Expression {
+        self.add(&other.multiply(&Expression::constant(-1)).unwrap())
+    }
+
+    pub fn eval(&self, marking: &Marking) -> i64 {
+        self.coefficients.iter().fold(self.constant, |value, &(p, c)| value + c * marking[p] as i64)
+    }
+
+    ///None if neither of the expressions is a constant (the product wouldn't be linear)
+    pub fn multiply(&self, other: &Expression) -> Option<Expression> {
+        let (expression, factor) = match (self.coefficients.is_empty(), other.coefficients.is_empty()) {
+            (true, _) => (other, self.constant),
+            (_, true) => (self, other.constant),
+            _ => return None,
+        };
+        Some(Expression {
+            coefficients: expression.coefficients.iter().map(|&(p, c)| (p, c * factor)).collect(),
+            constant: expression.constant * factor,
+        })
+    }
+
+    ///Expression with places looked up in the net. Products have to be linear, all their items
+    ///but one have to be constants.
+    pub fn from_integer(integer: &Integer, net: &PetriNet) -> Result<Expression, CheckerError> {
+        let compile = |items: &Vec<Integer>| {
+            items.iter().map(|item| Expression::from_integer(item, net)).collect::<Result<Vec<_>, _>>()
+        };
+        match integer {
+            &Integer::Constant(value) => Ok(Expression::constant(value)),
+            &Integer::Tokens(ref names) => {
+                let mut places = Vec::new();
+                for name in names {
+                    match net.places.get(&*name) {
+                        Some(&i) => places.push(i),
+                        None => return Err(CheckerError::UnknownPlace(name.clone())),
+                    }
+                }
+                Ok(Expression::tokens(&places))
+            }
+            &Integer::Sum(ref items) => {
+                Ok(compile(items)?.iter().fold(Expression::constant(0), |sum, item| sum.add(item)))
+            }
+            &Integer::Difference(ref items) => {
+                let items = compile(items)?;
+                let first = items.first().cloned().unwrap_or(Expression::constant(0));
+                Ok(items.iter().skip(1).fold(first, |difference, item| difference.subtract(item)))
+            }
+            &Integer::Product(ref items) => {
+                compile(items)?.iter().fold(Some(Expression::constant(1)), |product, item| {
+                    product.and_then(|product| product.multiply(item))
+                }).ok_or_else(|| CheckerError::UnsupportedFormula(format!("{}", integer)))
+            }
+        }
+    }
+
+    ///e.g. p0 + 2*p3 - 1, places are named by the function
+    fn write<F>(&self, f: &mut fmt::Formatter, place: F) -> fmt::Result where F: Fn(usize) -> String {
+        let mut terms: Vec<(i64, String)> = self.coefficients.iter().map(|&(p, c)| {
+            (c, if c.abs() == 1 { place(p) } else { format!("{}*{}", c.abs(), place(p)) })
+        }).collect();
+        if self.constant != 0 || terms.is_empty() {
+            terms.push((self.constant, format!("{}", self.constant.abs())));
+        }
+        for (i, &(c, ref term)) in terms.iter().enumerate() {
+            match (i, c < 0) {
+                (0, false) => write!(f, "{}", term)?,
+                (0, true) => write!(f, "-{}", term)?,
+                (_, false) => write!(f, " + {}", term)?,
+                (_, true) => write!(f, " - {}", term)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, |p| format!("m[{}]", p))
+    }
+}
+
+///Linear inequality over the marking: sum of coefficient * tokens in place is at least the bound
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Constraint {
+    pub coefficients: Vec<(usize, i64)>,
+    pub bound: i64,
+}
+
+impl Constraint {
+
+    ///left >= right + offset
+    fn at_least(left: &Expression, right: &Expression, offset: i64) -> Constraint {
+        let mut coefficients = left.coefficients.clone();
+        coefficients.extend(right.coefficients.iter().map(|&(place, c)| (place, -c)));
+        Constraint { coefficients: coefficients, bound: right.constant - left.constant + offset }
+    }
+
+    ///Constraint that holds exactly in the markings where this one doesn't (tokens are integers)
+    pub fn negate(&self) -> Constraint {
+        Constraint {
+            coefficients: self.coefficients.iter().map(|&(place, c)| (place, -c)).collect(),
+            bound: 1 - self.bound,
+        }
+    }
+
+    ///How much is the weighted sum of tokens below the bound (zero if the constraint holds)
+    pub fn missing(&self, marking: &Marking) -> i64 {
+        let mut value = 0;
+        for &(place, c) in &self.coefficients {
+            value += c * marking[place] as i64;
+        }
+        if value >= self.bound { 0 } else { self.bound - value }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    LT, LE, GT, GE,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Comparison::LT => "<",
+            Comparison::LE => "<=",
+            Comparison::GT => ">",
+            Comparison::GE => ">=",
+        })
+    }
+}
+
+///What the proposition looked like in the formula, constraints are compiled from it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Test {
+    Compare(Expression, Comparison, Expression),
+    Fireable(usize),
+    Constant(bool),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Proposition {
+    ///Places whose tokens are read by the proposition (including presets of tested transitions)
+    pub places: Vec<usize>,
+    ///Transitions whose enabledness is tested by the proposition
+    pub transitions: Vec<usize>,
+    ///The proposition holds exactly when all of the constraints hold
+    pub constraints: Vec<Constraint>,
+    pub test: Test,
+}
+
+impl Proposition {
+
+    ///Proposition with the same value in every marking
+    pub fn constant(value: bool) -> Proposition {
+        Proposition {
+            places: vec![],
+            transitions: vec![],
+            //false is represented by the unsatisfiable 0 >= 1
+            constraints: if value { vec![] } else { vec![Constraint { coefficients: vec![], bound: 1 }] },
+            test: Test::Constant(value),
+        }
+    }
+
+    ///Proposition left comparison right
+    pub fn compare(left: Expression, comparison: Comparison, right: Expression) -> Proposition {
+        let constraint = match comparison {
+            Comparison::LT => Constraint::at_least(&right, &left, 1),
+            Comparison::LE => Constraint::at_least(&right, &left, 0),
+            Comparison::GT => Constraint::at_least(&left, &right, 1),
+            Comparison::GE => Constraint::at_least(&left, &right, 0),
+        };
+        Proposition {
+            places: left.coefficients.iter().chain(right.coefficients.iter()).map(|&(p, _)| p).collect(),
+            transitions: vec![],
+            constraints: vec![constraint],
+            test: Test::Compare(left, comparison, right),
+        }
+    }
+
+    ///Proposition that holds exactly when this one doesn't. Fireability can't be negated, because
+    ///its negation isn't a conjunction of constraints.
+    pub fn negate(&self) -> Option<Proposition> {
+        match self.test {
+            Test::Compare(ref left, comparison, ref right) => {
+                let negated = match comparison {
+                    Comparison::LT => Comparison::GE,
+                    Comparison::LE => Comparison::GT,
+                    Comparison::GT => Comparison::LE,
+                    Comparison::GE => Comparison::LT,
+                };
+                Some(Proposition::compare(left.clone(), negated, right.clone()))
+            }
+            Test::Constant(value) => Some(Proposition::constant(!value)),
+            Test::Fireable(_) => None,
+        }
+    }
+
+    pub fn eval(&self, marking: &Marking) -> bool {
+        self.constraints.iter().all(|c| c.missing(marking) == 0)
+    }
+
+    ///Estimate of how far is the marking from satisfying the proposition: how many tokens have
+    ///to be added or removed (zero if it holds)
+    pub fn distance(&self, marking: &Marking) -> u32 {
+        let missing: i64 = self.constraints.iter().map(|c| c.missing(marking)).sum();
+        min(missing, u32::max_value() as i64) as u32
+    }
+}
+
+impl Proposition {
+
+    ///Write the proposition using given names of places and transitions, or their indices
+    fn write(&self, f: &mut fmt::Formatter, names: Option<&Names>) -> fmt::Result {
+        let place = |p: usize| names.map_or(format!("m[{}]", p), |n| n.places[p].to_string());
+        match self.test {
+            Test::Compare(ref left, comparison, ref right) => {
+                left.write(f, &place)?;
+                write!(f, " {} ", comparison)?;
+                right.write(f, &place)
+            }
+            Test::Fireable(t) => match names {
+                Some(names) => write!(f, "fireable({})", names.transitions[t]),
+                None => write!(f, "fireable(t[{}])", t),
+            },
+            Test::Constant(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Proposition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+impl fmt::Debug for Proposition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
 
 pub struct Query {
     pub id: QueryId,
@@ -21,6 +285,8 @@ pub enum Operator {
     Not(Box<Query>),
     EF(Box<Query>),
     AF(Box<Query>),
+    EG(Box<Query>),
+    AG(Box<Query>),
     EX(Box<Query>),
     AX(Box<Query>),
     And(Vec<Query>),
@@ -29,14 +295,15 @@ pub enum Operator {
     EU(Box<Query>, Box<Query>),
 }
 
-//we can't derive debug because propositions are closures
 impl fmt::Debug for Operator {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Op::Atom(_) => write!(f, "atom"),
+            &Op::Atom(ref proposition) => write!(f, "{}", proposition),
             &Op::Not(ref inner) => write!(f, "!({:?})", inner),
             &Op::EF(ref inner) => write!(f, "EF({:?})", inner),
             &Op::AF(ref inner) => write!(f, "AF({:?})", inner),
+            &Op::EG(ref inner) => write!(f, "EG({:?})", inner),
+            &Op::AG(ref inner) => write!(f, "AG({:?})", inner),
             &Op::EX(ref inner) => write!(f, "EX({:?})", inner),
             &Op::AX(ref inner) => write!(f, "AX({:?})", inner),
             &Op::And(ref items) => write!(f, "((&&) {:?})", items),
@@ -53,15 +320,211 @@ impl fmt::Debug for Query {
     }
 }
 
+struct Names<'a> {
+    places: Vec<&'a str>,
+    transitions: Vec<&'a str>,
+}
+
+///Query in CTL syntax with names of places and transitions taken from the net, optionally with
+///subquery ids, i.e. [3: EF([2: p3 + p4 <= 2])]
+pub struct PrettyQuery<'a> {
+    query: &'a Query,
+    names: Names<'a>,
+    ids: bool,
+}
+
+impl <'a> PrettyQuery<'a> {
+
+    fn write(&self, f: &mut fmt::Formatter, query: &Query) -> fmt::Result {
+        if self.ids {
+            write!(f, "[{}: ", query.id)?;
+        }
+        match query.operator {
+            Op::Atom(ref proposition) => proposition.write(f, Some(&self.names))?,
+            Op::Not(ref inner) => self.write_unary(f, "!", inner)?,
+            Op::EF(ref inner) => self.write_unary(f, "EF", inner)?,
+            Op::AF(ref inner) => self.write_unary(f, "AF", inner)?,
+            Op::EG(ref inner) => self.write_unary(f, "EG", inner)?,
+            Op::AG(ref inner) => self.write_unary(f, "AG", inner)?,
+            Op::EX(ref inner) => self.write_unary(f, "EX", inner)?,
+            Op::AX(ref inner) => self.write_unary(f, "AX", inner)?,
+            Op::And(ref items) => self.write_list(f, " && ", items)?,
+            Op::Or(ref items) => self.write_list(f, " || ", items)?,
+            Op::EU(ref left, ref right) => self.write_until(f, "E", left, right)?,
+            Op::AU(ref left, ref right) => self.write_until(f, "A", left, right)?,
+        }
+        if self.ids {
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+
+    fn write_unary(&self, f: &mut fmt::Formatter, operator: &str, inner: &Query) -> fmt::Result {
+        write!(f, "{}(", operator)?;
+        self.write(f, inner)?;
+        write!(f, ")")
+    }
+
+    fn write_list(&self, f: &mut fmt::Formatter, separator: &str, items: &Vec<Query>) -> fmt::Result {
+        if items.len() == 1 {     //i.e. fireability of one transition
+            return self.write(f, &items[0]);
+        }
+        write!(f, "(")?;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                write!(f, "{}", separator)?;
+            }
+            self.write(f, item)?;
+        }
+        write!(f, ")")
+    }
+
+    fn write_until(&self, f: &mut fmt::Formatter, quantifier: &str, left: &Query, right: &Query) -> fmt::Result {
+        write!(f, "{}(", quantifier)?;
+        self.write(f, left)?;
+        write!(f, " U ")?;
+        self.write(f, right)?;
+        write!(f, ")")
+    }
+}
+
+impl <'a> fmt::Display for PrettyQuery<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, self.query)
+    }
+}
+
+///Places and transitions observed by a query
+#[derive(Debug, Clone, Default)]
+pub struct Support {
+    pub places: HashSet<usize>,
+    pub transitions: HashSet<usize>,
+}
+
+impl Operator {
+
+    ///Direct subqueries of this operator
+    pub fn children(&self) -> Vec<&Query> {
+        match self {
+            &Op::Atom(_) => vec![],
+            &Op::Not(ref inner) | &Op::EF(ref inner) | &Op::AF(ref inner) | &Op::EG(ref inner) |
+            &Op::AG(ref inner) | &Op::EX(ref inner) | &Op::AX(ref inner) => vec![&**inner],
+            &Op::And(ref items) | &Op::Or(ref items) => items.iter().collect(),
+            &Op::EU(ref left, ref right) | &Op::AU(ref left, ref right) => vec![&**left, &**right],
+        }
+    }
+
+    pub fn is_temporal(&self) -> bool {
+        match self {
+            &Op::Atom(_) | &Op::Not(_) | &Op::And(_) | &Op::Or(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl Query {
+
+    ///Printable form of the query using names from the net the query was created for
+    pub fn pretty<'a>(&'a self, net: &'a PetriNet, ids: bool) -> PrettyQuery<'a> {
+        PrettyQuery {
+            query: self,
+            names: Names { places: net.place_names(), transitions: net.transition_names() },
+            ids: ids,
+        }
+    }
+
+    ///All places and transitions observed by the atomic propositions of this query
+    pub fn support(&self) -> Support {
+        let mut support = Support::default();
+        self.collect_support(&mut support);
+        support
+    }
+
+    fn collect_support(&self, support: &mut Support) {
+        if let Op::Atom(ref proposition) = self.operator {
+            support.places.extend(proposition.places.iter().cloned());
+            support.transitions.extend(proposition.transitions.iter().cloned());
+        }
+        for child in self.operator.children() {
+            child.collect_support(support);
+        }
+    }
+
+    ///True if there are no temporal operators in this query
+    pub fn is_state_formula(&self) -> bool {
+        !self.operator.is_temporal() && self.operator.children().into_iter().all(|c| c.is_state_formula())
+    }
+
+    ///Estimate of how far is the marking from satisfying this query. Only available for atoms
+    ///and their conjunctions and disjunctions.
+    pub fn distance(&self, marking: &Marking) -> Option<u32> {
+        match self.operator {
+            Op::Atom(ref proposition) => Some(proposition.distance(marking)),
+            Op::And(ref items) => items.iter().fold(Some(0), |acc, item| {
+                acc.and_then(|a| item.distance(marking).map(|d| a.saturating_add(d)))
+            }),
+            Op::Or(ref items) => items.iter().fold(Some(u32::max_value()), |acc, item| {
+                acc.and_then(|a| item.distance(marking).map(|d| min(a, d)))
+            }),
+            _ => None,
+        }
+    }
+
+    ///True if distance is available for this query
+    pub fn has_distance(&self) -> bool {
+        match self.operator {
+            Op::Atom(_) => true,
+            Op::And(ref items) | Op::Or(ref items) => items.iter().all(|i| i.has_distance()),
+            _ => false,
+        }
+    }
+
+    ///True for (possibly negated) EF/AG queries over state formulas, i.e. plain reachability
+    pub fn is_reachability(&self) -> bool {
+        match self.operator {
+            Op::Not(ref inner) => inner.is_reachability(),
+            Op::EF(ref inner) | Op::AG(ref inner) => inner.is_state_formula(),
+            _ => false,
+        }
+    }
+}
+
 impl Query {
 
-    pub fn from_formula(formula: &Formula, net: &PetriNet, next_id: QueryId) -> (Query, QueryId) {
+    ///Atomic query that is true (or false) in every marking
+    pub fn constant(value: bool, next_id: QueryId) -> (Query, QueryId) {
+        as_atom(Proposition::constant(value), next_id)
+    }
+
+    ///Query that holds in markings where no transition is enabled
+    pub fn deadlock(net: &PetriNet, next_id: QueryId) -> (Query, QueryId) {
+        let mut next_id = next_id;
+        let mut fireable = Vec::new();
+        for t in 0..net.matrix.len() {
+            let (query, id) = as_atom(fire_transition(t, net), next_id);
+            fireable.push(query);
+            next_id = id;
+        }
+        let any = Query { id: next_id, operator: Op::Or(fireable) };
+        (Query { id: next_id + 1, operator: Op::Not(Box::new(any)) }, next_id + 2)
+    }
+
+    ///Normalized query for the formula, this is what every checker should get
+    pub fn compile(formula: &Formula, net: &PetriNet) -> Result<Query, CheckerError> {
+        let (query, _) = Query::from_formula(formula, net, 0)?;
+        Ok(simplify(query))
+    }
+
+    pub fn from_formula(formula: &Formula, net: &PetriNet, next_id: QueryId) -> Result<(Query, QueryId), CheckerError> {
         match formula {
-            &LT(ref left, ref right) => as_atom(as_proposition(left, right, net, create_lt), next_id),
-            &LE(ref left, ref right) => as_atom(as_proposition(left, right, net, create_le), next_id),
-            &GT(ref left, ref right) => as_atom(as_proposition(left, right, net, create_gt), next_id),
-            &GE(ref left, ref right) => as_atom(as_proposition(left, right, net, create_ge), next_id),
+            &Constant(value) => Ok(Query::constant(value, next_id)),
+            &Deadlock => Ok(Query::deadlock(net, next_id)),
             &Fireable(ref transitions) => fire_proposition(transitions, net, next_id),
+            &Compare(ref left, comparison, ref right) => {
+                let left = Expression::from_integer(left, net)?;
+                let right = Expression::from_integer(right, net)?;
+                Ok(as_atom(Proposition::compare(left, comparison, right), next_id))
+            }
             &And(ref items) => as_binary_list_query(items, net, Op::And, next_id),
             &Or(ref items) => as_binary_list_query(items, net, Op::Or, next_id),
             &AU(ref left, ref right) => as_binary_query(left, right, net, Op::AU, next_id),
@@ -71,17 +534,8 @@ impl Query {
             &EX(ref inner) => as_unary_query(inner, net, Op::EX, next_id),
             &AF(ref inner) => as_unary_query(inner, net, Op::AF, next_id),
             &EF(ref inner) => as_unary_query(inner, net, Op::EF, next_id),
-            &AG(ref inner) => {
-                let (inner_not, next_id) = as_unary_query(inner, net, Op::Not, next_id);
-                let (ef, next_id) = (Query { id: next_id, operator: Op::EF(Box::new(inner_not)) }, next_id + 1);
-                (Query { id: next_id, operator: Op::Not(Box::new(ef)) }, next_id + 1)
-            }
-            &EG(ref inner) => {
-                let (inner_not, next_id) = as_unary_query(inner, net, Op::Not, next_id);
-                let (af, next_id) = (Query { id: next_id, operator: Op::AF(Box::new(inner_not)) }, next_id + 1);
-                (Query { id: next_id, operator: Op::Not(Box::new(af)) }, next_id + 1)
-            }
-            f => panic!("Unsupported formula {}", f),
+            &AG(ref inner) => as_unary_query(inner, net, Op::AG, next_id),
+            &EG(ref inner) => as_unary_query(inner, net, Op::EG, next_id),
         }
     }
 }
@@ -93,87 +547,55 @@ fn as_atom(prop: Proposition, next_id: QueryId) -> (Query, QueryId) {
 fn as_binary_list_query<F>(
     items: &Vec<Formula>,
     net: &PetriNet, combine: F, next_id: QueryId
-) -> (Query, QueryId) where F : Fn(Vec<Query>) -> Operator {
+) -> Result<(Query, QueryId), CheckerError> where F : Fn(Vec<Query>) -> Operator {
     let mut next_id = next_id;
     let mut formulas = Vec::new();
     for item in items {
-        let (query, id) = Query::from_formula(item, net, next_id);
+        let (query, id) = Query::from_formula(item, net, next_id)?;
         formulas.push(query);
         next_id = id;
     }
-    (Query { id: next_id, operator: combine(formulas) }, next_id + 1)
+    Ok((Query { id: next_id, operator: combine(formulas) }, next_id + 1))
 }
 
 fn as_binary_query<F>(
     left: &Box<Formula>, right: &Box<Formula>,
     net: &PetriNet, combine: F, next_id: QueryId
-) -> (Query, QueryId) where F : Fn(Box<Query>, Box<Query>) -> Operator {
-    let (r_query, next_id) = Query::from_formula(&*right, net, next_id);    //right side will have smaller ids
-    let (l_query, next_id) = Query::from_formula(&*left, net, next_id);
-    (Query { id: next_id, operator: combine(Box::new(l_query), Box::new(r_query)) }, next_id + 1)
+) -> Result<(Query, QueryId), CheckerError> where F : Fn(Box<Query>, Box<Query>) -> Operator {
+    let (r_query, next_id) = Query::from_formula(&*right, net, next_id)?;    //right side will have smaller ids
+    let (l_query, next_id) = Query::from_formula(&*left, net, next_id)?;
+    Ok((Query { id: next_id, operator: combine(Box::new(l_query), Box::new(r_query)) }, next_id + 1))
 }
 
-fn as_unary_query<F>(inner: &Box<Formula>, net: &PetriNet, combine: F, next_id: QueryId) -> (Query, QueryId)
+fn as_unary_query<F>(inner: &Box<Formula>, net: &PetriNet, combine: F, next_id: QueryId) -> Result<(Query, QueryId), CheckerError>
     where F : Fn(Box<Query>) -> Operator {
-    let (inner_query, next_id) = Query::from_formula(&*inner, net, next_id);
-    (Query { id: next_id, operator: combine(Box::new(inner_query)) }, next_id + 1)
+    let (inner_query, next_id) = Query::from_formula(&*inner, net, next_id)?;
+    Ok((Query { id: next_id, operator: combine(Box::new(inner_query)) }, next_id + 1))
 }
 
-fn as_proposition<F>(left: &Value, right: &Value, net: &PetriNet, combine: F) -> Proposition
-    where F : Fn(Evaluable, Evaluable) -> Proposition {
-    let l_eval = as_evaluable(left, net);
-    let r_eval = as_evaluable(right, net);
-    combine(l_eval, r_eval)
-}
-
-fn fire_proposition(transitions: &Vec<String>, net: &PetriNet, next_id: QueryId) -> (Query, QueryId) {
+fn fire_proposition(transitions: &Vec<String>, net: &PetriNet, next_id: QueryId) -> Result<(Query, QueryId), CheckerError> {
     let mut next_id = next_id;
     let mut inner = Vec::new();
     for t in transitions {
-        let (query, id) = as_atom(fire_transition(t, net), next_id);
+        let index = match net.transitions.get(&*t) {
+            Some(&index) => index,
+            None => return Err(CheckerError::UnknownTransition(t.clone())),
+        };
+        let (query, id) = as_atom(fire_transition(index, net), next_id);
         inner.push(query);
         next_id = id;
     }
-    (Query { id: next_id, operator: Op::Or(inner) }, next_id + 1)
-}
-
-fn fire_transition(t: &String, net: &PetriNet) -> Proposition {
-    if let Some(&index) = net.transitions.get(&*t) {
-        let vector = net.matrix[index].0.clone();
-        Box::new(move |m| {     //TODO can we do it without the clone?
-            vector.clone().into_iter().all(|(place, required)| required <= m[place])
-        })
-    } else {
-        panic!("Transition not found: {}", t)
-    }
+    Ok((Query { id: next_id, operator: Op::Or(inner) }, next_id + 1))
 }
 
-fn create_lt(left: Evaluable, right: Evaluable) -> Proposition {
-    Box::new(move |m| left(m) < right(m))
-}
-
-fn create_gt(left: Evaluable, right: Evaluable) -> Proposition {
-    Box::new(move |m| left(m) > right(m))
-}
-
-fn create_le(left: Evaluable, right: Evaluable) -> Proposition {
-    Box::new(move |m| left(m) <= right(m))
-}
-
-fn create_ge(left: Evaluable, right: Evaluable) -> Proposition {
-    Box::new(move |m| left(m) >= right(m))
-}
-
-fn as_evaluable(value: &Value, net: &PetriNet) -> Evaluable {
-    match value {
-        &Const(v) => Box::new(move |_| v),
-        &Ref(ref names) => {
-            let indices = names.into_iter().filter_map(|name| net.places.get(&*name)).map(|i| i.clone()).collect::<Vec<usize>>();
-            if indices.len() != names.len() {
-                panic!("Some place not found in {:?}", names);
-            } else {    //TODO: Can we do it without clone?
-                Box::new(move |m| indices.clone().into_iter().map(|i| m[i]).fold(0, |a, b| a + b))
-            }
-        }
+fn fire_transition(index: usize, net: &PetriNet) -> Proposition {
+    let ref preset = net.matrix[index].0;
+    Proposition {
+        places: preset.iter().map(|&(place, _)| place).collect(),
+        transitions: vec![index],
+        constraints: preset.iter().map(|&(place, required)| {
+            Constraint { coefficients: vec![(place, 1)], bound: required as i64 }
+        }).collect(),
+        test: Test::Fireable(index),
     }
 }
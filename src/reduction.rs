@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use petri_net::*;
+use query::*;
+
+///Result of structural reduction. Names of the remaining places and transitions are kept, so the
+///query has to be compiled again against the reduced net. Fused transitions are named after all
+///the transitions they replace, i.e. "t1+t2".
+pub struct Reduction {
+    pub net: PetriNet,
+    pub removed_places: usize,
+    pub removed_transitions: usize,
+}
+
+//Working copy of the net. Places and transitions keep their original indices
+//and are only marked as removed until the reduced net is built.
+struct Reducer<'a> {
+    net: &'a PetriNet,
+    support: Support,
+    initial_marking: Marking,
+    matrix: Vec<(Vec<(usize, u32)>, Vec<(usize, u32)>)>,
+    transition_names: Vec<String>,
+    place_alive: Vec<bool>,
+    transition_alive: Vec<bool>,
+}
+
+///Reduce the net while preserving the answer of the given query.
+///
+///Rules that are safe for any CTL query:
+/// - dead transitions (transitions that can never become enabled) are removed
+/// - places not observed by the query which never disable any transition are removed
+///
+///For plain reachability queries (EF/AG of a state formula) also:
+/// - sequences p -> t2 where t2 only waits for p and p is only filled by t1 are agglomerated,
+///   i.e. t2 is fired together with t1 (pre-agglomeration)
+/// - places p that are only filled by transitions H and emptied by transitions F which wait for
+///   nothing else are removed and every h in H is fused with every f in F (post-agglomeration,
+///   only applied when it doesn't add transitions)
+/// - a transition that only moves a token from p1 to p2 and is the only one consuming from p1 is
+///   removed and p1 is fused into p2 (fusion of series places)
+///
+///These rules skip the intermediate markings of the fused sequences, which can be observed by
+///next-state operators, deadlocks and fair paths, so other queries only get the first two rules.
+pub fn reduce(net: &PetriNet, query: &Query) -> Reduction {
+    let mut reducer = Reducer {
+        net: net,
+        support: query.support(),
+        initial_marking: net.initial_marking.clone(),
+        matrix: net.matrix.clone(),
+        transition_names: names_by_index(&net.transitions),
+        place_alive: vec![true; net.places.len()],
+        transition_alive: vec![true; net.transitions.len()],
+    };
+    let reachability = query.is_reachability();
+    loop {
+        let mut changed = reducer.remove_dead_transitions();
+        changed |= reducer.remove_unobserved_places();
+        if reachability {
+            changed |= reducer.fuse_sequences();
+            changed |= reducer.post_agglomerate();
+            changed |= reducer.fuse_series_places();
+        }
+        if !changed {
+            break;
+        }
+    }
+    reducer.build()
+}
+
+fn names_by_index(names: &HashMap<String, usize>) -> Vec<String> {
+    let mut result = vec![String::new(); names.len()];
+    for (name, &index) in names {
+        result[index] = name.clone();
+    }
+    result
+}
+
+fn weight(arcs: &Vec<(usize, u32)>, place: usize) -> u32 {
+    arcs.iter().filter(|&&(p, _)| p == place).map(|&(_, w)| w).sum()
+}
+
+///Add weights of the other arcs to the arcs, merging arcs to the same place
+fn add_arcs(arcs: &mut Vec<(usize, u32)>, other: &Vec<(usize, u32)>) {
+    for &(q, w) in other {
+        if let Some(arc) = arcs.iter_mut().find(|arc| arc.0 == q) {
+            arc.1 += w;
+            continue;
+        }
+        arcs.push((q, w));
+    }
+}
+
+impl <'a> Reducer<'a> {
+
+    fn alive_transitions(&self) -> Vec<usize> {
+        (0..self.matrix.len()).filter(|&t| self.transition_alive[t]).collect()
+    }
+
+    fn remove_place(&mut self, place: usize) {
+        self.place_alive[place] = false;
+        for &mut (ref mut pre, ref mut post) in &mut self.matrix {
+            pre.retain(|&(p, _)| p != place);
+            post.retain(|&(p, _)| p != place);
+        }
+    }
+
+    ///Find places that can ever hold a token and remove transitions that need some other place.
+    fn remove_dead_transitions(&mut self) -> bool {
+        let mut markable: Vec<bool> = self.initial_marking.iter().map(|&m| m > 0).collect();
+        let mut enabled = vec![false; self.matrix.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for t in self.alive_transitions() {
+                if !enabled[t] && self.matrix[t].0.iter().all(|&(p, _)| markable[p]) {
+                    enabled[t] = true;
+                    changed = true;
+                    for &(p, _) in &self.matrix[t].1 {
+                        markable[p] = true;
+                    }
+                }
+            }
+        }
+        let mut removed = false;
+        for t in self.alive_transitions() {
+            if !enabled[t] && !self.support.transitions.contains(&t) {
+                self.transition_alive[t] = false;
+                removed = true;
+            }
+        }
+        removed
+    }
+
+    ///Remove places that are not observed and never disable a transition, i.e. every transition
+    ///consuming from the place puts at least as much back and the initial marking is sufficient.
+    fn remove_unobserved_places(&mut self) -> bool {
+        let mut removed = false;
+        for p in 0..self.place_alive.len() {
+            if !self.place_alive[p] || self.support.places.contains(&p) {
+                continue;
+            }
+            let initial = self.initial_marking[p];
+            let never_disables = self.alive_transitions().into_iter().all(|t| {
+                let consumed = weight(&self.matrix[t].0, p);
+                consumed <= initial && consumed <= weight(&self.matrix[t].1, p)
+            });
+            if never_disables {
+                self.remove_place(p);
+                removed = true;
+            }
+        }
+        removed
+    }
+
+    ///Pre-agglomeration: if place p is initially empty, filled only by t1 and emptied only by t2
+    ///which needs nothing else, t2 can be fired immediately after t1. Valid for reachability as
+    ///long as p, t1, t2 and the outputs of t2 are not observed.
+    fn fuse_sequences(&mut self) -> bool {
+        let mut fused = false;
+        for p in 0..self.place_alive.len() {
+            if !self.place_alive[p] || self.support.places.contains(&p) || self.initial_marking[p] > 0 {
+                continue;
+            }
+            let alive = self.alive_transitions();
+            let producers: Vec<usize> = alive.iter().cloned().filter(|&t| weight(&self.matrix[t].1, p) > 0).collect();
+            let consumers: Vec<usize> = alive.iter().cloned().filter(|&t| weight(&self.matrix[t].0, p) > 0).collect();
+            if producers.len() != 1 || consumers.len() != 1 {
+                continue;
+            }
+            let (t1, t2) = (producers[0], consumers[0]);
+            let fusable = t1 != t2 &&
+                !self.support.transitions.contains(&t1) && !self.support.transitions.contains(&t2) &&
+                weight(&self.matrix[t1].1, p) == 1 && weight(&self.matrix[t1].0, p) == 0 &&
+                self.matrix[t2].0 == vec![(p, 1)] && weight(&self.matrix[t2].1, p) == 0 &&
+                self.matrix[t2].1.iter().all(|&(q, _)| !self.support.places.contains(&q));
+            if !fusable {
+                continue;
+            }
+            let outputs = self.matrix[t2].1.clone();
+            add_arcs(&mut self.matrix[t1].1, &outputs);
+            self.transition_names[t1] = format!("{}+{}", self.transition_names[t1], self.transition_names[t2]);
+            self.transition_alive[t2] = false;
+            self.remove_place(p);
+            fused = true;
+        }
+        fused
+    }
+
+    ///Post-agglomeration: if place p is initially empty, every producer puts one token into it and
+    ///every consumer only takes one token from p, a consumer can always be fired right after the
+    ///producer. Each producer is replaced by its fusions with all the consumers. Valid for
+    ///reachability as long as p, the fused transitions and the outputs of consumers are not observed.
+    fn post_agglomerate(&mut self) -> bool {
+        let mut fused = false;
+        for p in 0..self.place_alive.len() {
+            if !self.place_alive[p] || self.support.places.contains(&p) || self.initial_marking[p] > 0 {
+                continue;
+            }
+            let alive = self.alive_transitions();
+            let producers: Vec<usize> = alive.iter().cloned().filter(|&t| weight(&self.matrix[t].1, p) > 0).collect();
+            let consumers: Vec<usize> = alive.iter().cloned().filter(|&t| weight(&self.matrix[t].0, p) > 0).collect();
+            if producers.is_empty() || consumers.is_empty() ||
+                producers.len() * consumers.len() > producers.len() + consumers.len() {
+                continue;
+            }
+            let fusable = producers.iter().all(|&h| {
+                !self.support.transitions.contains(&h) &&
+                    weight(&self.matrix[h].1, p) == 1 && weight(&self.matrix[h].0, p) == 0
+            }) && consumers.iter().all(|&f| {
+                !self.support.transitions.contains(&f) &&
+                    self.matrix[f].0 == vec![(p, 1)] && weight(&self.matrix[f].1, p) == 0 &&
+                    self.matrix[f].1.iter().all(|&(q, _)| !self.support.places.contains(&q))
+            });
+            if !fusable {
+                continue;
+            }
+            for &h in &producers {
+                let (pre, mut post) = self.matrix[h].clone();
+                post.retain(|&(q, _)| q != p);
+                let name = self.transition_names[h].clone();
+                for (i, &f) in consumers.iter().enumerate() {
+                    let mut fused_post = post.clone();
+                    add_arcs(&mut fused_post, &self.matrix[f].1);
+                    let fused_name = format!("{}+{}", name, self.transition_names[f]);
+                    //the first fusion takes the place of the producer
+                    let index = if i == 0 { h } else {
+                        self.matrix.push((vec![], vec![]));
+                        self.transition_names.push(String::new());
+                        self.transition_alive.push(true);
+                        self.matrix.len() - 1
+                    };
+                    self.matrix[index] = (pre.clone(), fused_post);
+                    self.transition_names[index] = fused_name;
+                }
+            }
+            for &f in &consumers {
+                self.transition_alive[f] = false;
+            }
+            self.remove_place(p);
+            fused = true;
+        }
+        fused
+    }
+
+    ///Fusion of series places: if transition t only moves one token from p1 to p2 and no other
+    ///transition consumes from p1, tokens can be moved to p2 as soon as they arrive to p1. Transition
+    ///t is removed and p1 is merged into p2. Valid for reachability as long as p1, p2 and t are not
+    ///observed.
+    fn fuse_series_places(&mut self) -> bool {
+        let mut fused = false;
+        for t in self.alive_transitions() {
+            if !self.transition_alive[t] || self.support.transitions.contains(&t) {
+                continue;
+            }
+            let (p1, p2) = match (&self.matrix[t].0[..], &self.matrix[t].1[..]) {
+                (&[(p1, 1)], &[(p2, 1)]) if p1 != p2 => (p1, p2),
+                _ => continue,
+            };
+            if self.support.places.contains(&p1) || self.support.places.contains(&p2) {
+                continue;
+            }
+            let only_consumer = self.alive_transitions().into_iter().all(|u| u == t || weight(&self.matrix[u].0, p1) == 0);
+            if !only_consumer {
+                continue;
+            }
+            for u in self.alive_transitions() {
+                let moved = weight(&self.matrix[u].1, p1);
+                if moved > 0 {
+                    self.matrix[u].1.retain(|&(q, _)| q != p1);
+                    add_arcs(&mut self.matrix[u].1, &vec![(p2, moved)]);
+                }
+            }
+            self.initial_marking[p2] += self.initial_marking[p1];
+            self.initial_marking[p1] = 0;
+            self.transition_alive[t] = false;
+            self.remove_place(p1);
+            fused = true;
+        }
+        fused
+    }
+
+    fn build(self) -> Reduction {
+        let mut place_index = vec![0; self.place_alive.len()];
+        let mut places = HashMap::new();
+        let mut initial_marking = Vec::new();
+        let place_names = names_by_index(&self.net.places);
+        for p in 0..self.place_alive.len() {
+            if self.place_alive[p] {
+                place_index[p] = initial_marking.len();
+                places.insert(place_names[p].clone(), initial_marking.len());
+                initial_marking.push(self.initial_marking[p]);
+            }
+        }
+        let mut transitions = HashMap::new();
+        let mut matrix = Vec::new();
+        for t in 0..self.matrix.len() {
+            if self.transition_alive[t] {
+                let remap = |arcs: &Vec<(usize, u32)>| arcs.iter().map(|&(p, w)| (place_index[p], w)).collect();
+                transitions.insert(self.transition_names[t].clone(), matrix.len());
+                matrix.push((remap(&self.matrix[t].0), remap(&self.matrix[t].1)));
+            }
+        }
+        Reduction {
+            removed_places: self.net.places.len() - places.len(),
+            removed_transitions: self.net.transitions.len() - transitions.len(),
+            net: PetriNet {
+                places: places,
+                transitions: transitions,
+                initial_marking: initial_marking,
+                matrix: matrix,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formula::{Formula, Integer};
+
+    fn net(places: usize, initial_marking: Marking, matrix: Vec<(Vec<(usize, u32)>, Vec<(usize, u32)>)>) -> PetriNet {
+        PetriNet {
+            places: (0..places).map(|p| (format!("p{}", p), p)).collect(),
+            transitions: (0..matrix.len()).map(|t| (format!("t{}", t), t)).collect(),
+            initial_marking: initial_marking,
+            matrix: matrix,
+        }
+    }
+
+    ///Sum of tokens in the places is at least value
+    fn at_least(places: &[usize], value: i64) -> Formula {
+        let places = places.iter().map(|p| format!("p{}", p)).collect();
+        Formula::Compare(Integer::Tokens(places), Comparison::GE, Integer::Constant(value))
+    }
+
+    fn fireable(t: usize) -> Formula {
+        Formula::Fireable(vec![format!("t{}", t)])
+    }
+
+    fn reduce_for(net: &PetriNet, formula: Formula) -> Reduction {
+        reduce(net, &Query::compile(&formula, net).unwrap())
+    }
+
+    fn sorted_names(names: &HashMap<String, usize>) -> Vec<String> {
+        let mut names: Vec<String> = names.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    ///t0 takes a token from p0 and reads p1 (initially marked), p1 never disables t0
+    fn read_arc() -> PetriNet {
+        net(3, vec![1, 1, 0], vec![(vec![(0, 1), (1, 1)], vec![(1, 1), (2, 1)])])
+    }
+
+    #[test]
+    fn unobserved_place_is_removed() {
+        let net = read_arc();
+        let reduction = reduce_for(&net, Formula::EX(Box::new(at_least(&[2], 1))));
+        assert_eq!(sorted_names(&reduction.net.places), vec!["p0", "p2"]);
+        assert_eq!(reduction.net.matrix, vec![(vec![(0, 1)], vec![(1, 1)])]);
+    }
+
+    #[test]
+    fn observed_place_is_kept() {
+        let net = read_arc();
+        let observed = Formula::EX(Box::new(at_least(&[1, 2], 1)));
+        assert_eq!(reduce_for(&net, observed).removed_places, 0);
+        //p2 is not observed by fireability of t0 and nothing consumes from it
+        let tested = Formula::EX(Box::new(fireable(0)));
+        assert_eq!(sorted_names(&reduce_for(&net, tested).net.places), vec!["p0", "p1"]);
+    }
+
+    ///t0 moves a token from p0 to p1, t1 would do the same from p2 but p2 never gets a token
+    fn dead_transition() -> PetriNet {
+        net(3, vec![1, 0, 0], vec![(vec![(0, 1)], vec![(1, 1)]), (vec![(2, 1)], vec![(1, 1)])])
+    }
+
+    #[test]
+    fn dead_transition_is_removed() {
+        let net = dead_transition();
+        let reduction = reduce_for(&net, Formula::EX(Box::new(at_least(&[1], 1))));
+        assert_eq!(sorted_names(&reduction.net.transitions), vec!["t0"]);
+        assert_eq!(reduction.removed_transitions, 1);
+    }
+
+    #[test]
+    fn tested_dead_transition_is_kept() {
+        let net = dead_transition();
+        let reduction = reduce_for(&net, Formula::EX(Box::new(fireable(1))));
+        assert_eq!(sorted_names(&reduction.net.transitions), vec!["t0", "t1"]);
+    }
+
+    ///p0 -> t0 -> p1 -> t1 -> p2
+    fn chain() -> PetriNet {
+        net(3, vec![1, 0, 0], vec![(vec![(0, 1)], vec![(1, 1)]), (vec![(1, 1)], vec![(2, 1)])])
+    }
+
+    #[test]
+    fn series_places_are_fused() {
+        let net = chain();
+        let reduction = reduce_for(&net, Formula::EF(Box::new(at_least(&[2], 1))));
+        assert_eq!(sorted_names(&reduction.net.places), vec!["p1", "p2"]);
+        assert_eq!(sorted_names(&reduction.net.transitions), vec!["t1"]);
+        let p1 = reduction.net.places["p1"];
+        assert_eq!(reduction.net.initial_marking[p1], 1);
+    }
+
+    #[test]
+    fn observed_series_places_are_kept() {
+        let net = chain();
+        let observed = Formula::EF(Box::new(at_least(&[0, 2], 1)));
+        assert_eq!(reduce_for(&net, observed).removed_transitions, 0);
+        let tested = Formula::EF(Box::new(Formula::And(vec![fireable(0), at_least(&[2], 1)])));
+        assert_eq!(reduce_for(&net, tested).removed_transitions, 0);
+    }
+
+    #[test]
+    fn sequence_is_agglomerated() {
+        let net = chain();
+        let reduction = reduce_for(&net, Formula::EF(Box::new(at_least(&[0], 1))));
+        assert_eq!(sorted_names(&reduction.net.places), vec!["p0"]);
+        assert_eq!(sorted_names(&reduction.net.transitions), vec!["t0+t1"]);
+        //intermediate markings are observable by other queries
+        let next = Formula::EX(Box::new(at_least(&[0], 1)));
+        assert_eq!(sorted_names(&reduce_for(&net, next).net.transitions), vec!["t0", "t1"]);
+    }
+
+    #[test]
+    fn observed_sequence_is_kept() {
+        //t0 takes two tokens, so p0 is not fused into p1 either
+        let net = net(3, vec![2, 0, 0], vec![(vec![(0, 2)], vec![(1, 1)]), (vec![(1, 1)], vec![(2, 1)])]);
+        let observed = Formula::EF(Box::new(at_least(&[2], 1)));
+        assert_eq!(sorted_names(&reduce_for(&net, observed).net.transitions), vec!["t0", "t1"]);
+        let tested = Formula::EF(Box::new(fireable(1)));
+        assert_eq!(sorted_names(&reduce_for(&net, tested).net.transitions), vec!["t0", "t1"]);
+    }
+
+    ///Producers t0 (from p0) and t1 (from p1) put a token into p2, consumers t2 and t3 move it to p3
+    ///or p4
+    fn choice() -> PetriNet {
+        net(5, vec![1, 1, 0, 0, 0], vec![
+            (vec![(0, 1)], vec![(2, 1)]),
+            (vec![(1, 1)], vec![(2, 1)]),
+            (vec![(2, 1)], vec![(3, 1)]),
+            (vec![(2, 1)], vec![(4, 1)]),
+        ])
+    }
+
+    #[test]
+    fn producers_and_consumers_are_agglomerated() {
+        let net = choice();
+        let reduction = reduce_for(&net, Formula::EF(Box::new(at_least(&[0, 1], 2))));
+        assert_eq!(sorted_names(&reduction.net.places), vec!["p0", "p1"]);
+        assert_eq!(sorted_names(&reduction.net.transitions), vec!["t0+t2", "t0+t3", "t1+t2", "t1+t3"]);
+    }
+
+    #[test]
+    fn observed_producers_and_consumers_are_kept() {
+        let net = choice();
+        let observed = Formula::EF(Box::new(at_least(&[0, 1, 3], 2)));
+        assert_eq!(reduce_for(&net, observed).removed_transitions, 0);
+        let tested = Formula::EF(Box::new(Formula::And(vec![at_least(&[0, 1], 2), fireable(2)])));
+        assert_eq!(reduce_for(&net, tested).removed_transitions, 0);
+    }
+}
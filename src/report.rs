@@ -0,0 +1,150 @@
+use std::time::Duration;
+use ctl_checker::{CheckerError, Verdict};
+
+///Everything we know about a finished query
+pub struct QueryResult {
+    ///Query identifier from the query file. Queries without one get an id composed from a name
+    ///prefix and the position of the query in the file.
+    pub id: String,
+    pub formula: String,
+    pub verdict: Verdict,
+    pub markings: usize,
+    pub time: Duration,
+    pub techniques: Vec<&'static str>,
+    pub trace: Option<String>,
+}
+
+pub trait Reporter {
+    fn report(&mut self, result: &QueryResult);
+    ///Query could not be checked at all (i.e. it is malformed)
+    fn error(&mut self, id: &str, formula: &str, error: &CheckerError);
+}
+
+///Create reporter for given output format name (text, mcc, json or csv)
+pub fn create(format: &str) -> Option<Box<Reporter>> {
+    match format {
+        "text" => Some(Box::new(TextReporter)),
+        "mcc" => Some(Box::new(MccReporter)),
+        "json" => Some(Box::new(JsonReporter)),
+        "csv" => Some(Box::new(CsvReporter { header_printed: false })),
+        _ => None,
+    }
+}
+
+fn millis(time: &Duration) -> u64 {
+    time.as_secs() * 1000 + (time.subsec_nanos() / 1_000_000) as u64
+}
+
+///Human readable output
+pub struct TextReporter;
+
+impl Reporter for TextReporter {
+    fn report(&mut self, result: &QueryResult) {
+        println!("Query: {}", result.formula);
+        println!("Result: {}", result.verdict);
+        if let Some(ref trace) = result.trace {
+            println!("Trace: {}", trace);
+        }
+    }
+
+    fn error(&mut self, _id: &str, formula: &str, error: &CheckerError) {
+        println!("Query: {}", formula);
+        println!("Error: {}", error);
+    }
+}
+
+///Model checking contest output, i.e. FORMULA <id> TRUE TECHNIQUES EXPLICIT (place-bound
+///queries print the bound instead of TRUE)
+pub struct MccReporter;
+
+impl Reporter for MccReporter {
+    fn report(&mut self, result: &QueryResult) {
+        let verdict = match result.verdict {
+            Verdict::True => "TRUE".to_string(),
+            Verdict::False => "FALSE".to_string(),
+            Verdict::Bound(value) => format!("{}", value),
+            Verdict::CannotCompute => {
+                eprintln!("{}: limits exceeded", result.id);
+                return;
+            }
+        };
+        println!("FORMULA {} {} TECHNIQUES {}", result.id, verdict, result.techniques.join(" "));
+    }
+
+    ///Unanswered formulas simply don't get a FORMULA line
+    fn error(&mut self, id: &str, _formula: &str, error: &CheckerError) {
+        eprintln!("{}: {}", id, error);
+    }
+}
+
+///One JSON object per line
+pub struct JsonReporter;
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl Reporter for JsonReporter {
+    fn report(&mut self, result: &QueryResult) {
+        let trace = result.trace.as_ref().map(|t| json_string(t)).unwrap_or("null".to_string());
+        let verdict = match result.verdict {
+            Verdict::True => "true".to_string(),
+            Verdict::False => "false".to_string(),
+            Verdict::Bound(value) => format!("{}", value),
+            Verdict::CannotCompute => "null".to_string(),
+        };
+        println!("{{\"id\": {}, \"formula\": {}, \"verdict\": {}, \"markings\": {}, \"time_ms\": {}, \"trace\": {}}}",
+                 json_string(&result.id), json_string(&result.formula), verdict,
+                 result.markings, millis(&result.time), trace);
+    }
+
+    fn error(&mut self, id: &str, formula: &str, error: &CheckerError) {
+        println!("{{\"id\": {}, \"formula\": {}, \"error\": {}}}",
+                 json_string(id), json_string(formula), json_string(&format!("{}", error)));
+    }
+}
+
+///Comma separated values with a header line
+pub struct CsvReporter {
+    header_printed: bool,
+}
+
+fn csv_string(value: &str) -> String {
+    format!("\"{}\"", value.replace("\"", "\"\""))
+}
+
+impl CsvReporter {
+    fn header(&mut self) {
+        if !self.header_printed {
+            println!("id,verdict,markings,time_ms,formula,trace");
+            self.header_printed = true;
+        }
+    }
+}
+
+impl Reporter for CsvReporter {
+    fn report(&mut self, result: &QueryResult) {
+        self.header();
+        println!("{},{},{},{},{},{}", csv_string(&result.id), result.verdict, result.markings,
+                 millis(&result.time), csv_string(&result.formula),
+                 result.trace.as_ref().map(|t| csv_string(t)).unwrap_or(String::new()));
+    }
+
+    fn error(&mut self, id: &str, formula: &str, error: &CheckerError) {
+        self.header();
+        println!("{},error,,,{},{}", csv_string(id), csv_string(formula), csv_string(&format!("{}", error)));
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use query::*;
+use query::Operator::*;
+
+///Subquery identity: the proposition of an atom or the operator with ids of its children
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Atom(Proposition),
+    Operator(&'static str, Vec<QueryId>),
+}
+
+///Assigns the same id to structurally identical subqueries of all queries passed to it, so that
+///a graph can keep their values between searches and evaluate each of them only once.
+pub struct SubqueryTable {
+    ids: HashMap<Key, QueryId>,
+}
+
+impl SubqueryTable {
+
+    pub fn new() -> SubqueryTable {
+        SubqueryTable { ids: HashMap::new() }
+    }
+
+    ///Number of distinct subqueries seen so far
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    ///Renumber the query (children first) using ids of the previously shared queries
+    pub fn share(&mut self, query: &mut Query) {
+        let key = match query.operator {
+            Atom(ref proposition) => Key::Atom(proposition.clone()),
+            Not(ref mut inner) => self.unary("!", inner),
+            EF(ref mut inner) => self.unary("EF", inner),
+            AF(ref mut inner) => self.unary("AF", inner),
+            EG(ref mut inner) => self.unary("EG", inner),
+            AG(ref mut inner) => self.unary("AG", inner),
+            EX(ref mut inner) => self.unary("EX", inner),
+            AX(ref mut inner) => self.unary("AX", inner),
+            And(ref mut items) => self.list("&&", items),
+            Or(ref mut items) => self.list("||", items),
+            EU(ref mut left, ref mut right) => self.until("EU", left, right),
+            AU(ref mut left, ref mut right) => self.until("AU", left, right),
+        };
+        let next_id = self.ids.len();
+        query.id = *self.ids.entry(key).or_insert(next_id);
+    }
+
+    fn unary(&mut self, operator: &'static str, inner: &mut Query) -> Key {
+        self.share(inner);
+        Key::Operator(operator, vec![inner.id])
+    }
+
+    fn list(&mut self, operator: &'static str, items: &mut Vec<Query>) -> Key {
+        for item in items.iter_mut() {
+            self.share(item);
+        }
+        Key::Operator(operator, items.iter().map(|i| i.id).collect())
+    }
+
+    fn until(&mut self, operator: &'static str, left: &mut Query, right: &mut Query) -> Key {
+        self.share(right);
+        self.share(left);
+        Key::Operator(operator, vec![left.id, right.id])
+    }
+}
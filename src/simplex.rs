@@ -0,0 +1,223 @@
+use std::cmp::Ordering;
+
+///Problems with more tableau entries are not solved
+const MAX_ENTRIES: usize = 1_000_000;
+
+///Exact fraction, the denominator is always positive and the fraction is reduced. Operations return
+///None when they would overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+impl Rational {
+
+    fn integer(value: i64) -> Rational {
+        Rational { num: value as i128, den: 1 }
+    }
+
+    fn new(num: i128, den: i128) -> Option<Rational> {
+        let g = gcd(num, den);
+        if g == 0 {
+            return None;
+        }
+        let (num, den) = if den < 0 { (num.checked_neg()?, den.checked_neg()?) } else { (num, den) };
+        Some(Rational { num: num / g, den: den / g })
+    }
+
+    fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    fn signum(&self) -> i128 {
+        self.num.signum()
+    }
+
+    fn add(&self, other: &Rational) -> Option<Rational> {
+        if self.den == other.den {
+            return Rational::new(self.num.checked_add(other.num)?, self.den);
+        }
+        let num = self.num.checked_mul(other.den)?.checked_add(other.num.checked_mul(self.den)?)?;
+        Rational::new(num, self.den.checked_mul(other.den)?)
+    }
+
+    fn sub(&self, other: &Rational) -> Option<Rational> {
+        self.add(&Rational { num: other.num.checked_neg()?, den: other.den })
+    }
+
+    fn mul(&self, other: &Rational) -> Option<Rational> {
+        //cross reduce first to keep the numbers small
+        let g1 = gcd(self.num, other.den).max(1);
+        let g2 = gcd(other.num, self.den).max(1);
+        let num = (self.num / g1).checked_mul(other.num / g2)?;
+        let den = (self.den / g2).checked_mul(other.den / g1)?;
+        Rational::new(num, den)
+    }
+
+    fn div(&self, other: &Rational) -> Option<Rational> {
+        self.mul(&Rational::new(other.den, other.num)?)
+    }
+
+    fn compare(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.num.checked_mul(other.den)?.cmp(&other.num.checked_mul(self.den)?))
+    }
+}
+
+///Decide if there is a non-negative real vector x such that row * x >= bound for every row,
+///using the first phase of the simplex method with Bland's rule in exact arithmetic.
+///Returns None when the problem is too big or the numbers overflow.
+pub fn is_feasible(rows: &Vec<Vec<i64>>, bounds: &Vec<i64>) -> Option<bool> {
+    let variables = rows.first().map(|r| r.len()).unwrap_or(0);
+    let height = rows.len();
+    //every row gets a surplus variable, rows with positive bound also an artificial one
+    let artificial_count = bounds.iter().filter(|&&b| b > 0).count();
+    let width = variables + height + artificial_count;
+    if height * width > MAX_ENTRIES {
+        return None;
+    }
+    let zero = Rational::integer(0);
+    let mut table = vec![vec![zero; width]; height];
+    let mut rhs = Vec::with_capacity(height);
+    let mut basis = Vec::with_capacity(height);
+    let mut artificial = variables + height;
+    for (i, row) in rows.iter().enumerate() {
+        //row * x - s = bound, negated when bound isn't positive so that s can start in the basis
+        let sign = if bounds[i] > 0 { 1 } else { -1 };
+        for (j, &a) in row.iter().enumerate() {
+            table[i][j] = Rational::integer(sign * a);
+        }
+        table[i][variables + i] = Rational::integer(-sign);
+        rhs.push(Rational::integer(sign * bounds[i]));
+        if sign > 0 {
+            table[i][artificial] = Rational::integer(1);
+            basis.push(artificial);
+            artificial += 1;
+        } else {
+            basis.push(variables + i);
+        }
+    }
+    //minimize the sum of artificial variables, cost holds the reduced costs
+    let mut cost = vec![zero; width];
+    let mut objective = zero;
+    for i in 0..height {
+        if basis[i] >= variables + height {
+            for j in 0..(variables + height) {
+                cost[j] = cost[j].sub(&table[i][j])?;
+            }
+            objective = objective.add(&rhs[i])?;
+        }
+    }
+    loop {
+        if objective.is_zero() {
+            return Some(true);
+        }
+        let entering = match (0..width).find(|&j| cost[j].signum() < 0) {
+            Some(j) => j,
+            None => return Some(false),     //optimal with positive artificial variables
+        };
+        let mut leaving: Option<(usize, Rational)> = None;
+        for i in 0..height {
+            if table[i][entering].signum() > 0 {
+                let ratio = rhs[i].div(&table[i][entering])?;
+                let better = match leaving {
+                    None => true,
+                    Some((l, ref best)) => match ratio.compare(best)? {
+                        Ordering::Less => true,
+                        Ordering::Equal => basis[i] < basis[l],
+                        Ordering::Greater => false,
+                    },
+                };
+                if better {
+                    leaving = Some((i, ratio));
+                }
+            }
+        }
+        let (pivot_row, step) = match leaving {
+            Some(l) => l,
+            None => return None,    //can't happen, the objective is bounded
+        };
+        let pivot = table[pivot_row][entering];
+        for j in 0..width {
+            if !table[pivot_row][j].is_zero() {
+                table[pivot_row][j] = table[pivot_row][j].div(&pivot)?;
+            }
+        }
+        rhs[pivot_row] = step;
+        for i in 0..height {
+            let factor = table[i][entering];
+            if i == pivot_row || factor.is_zero() {
+                continue;
+            }
+            for j in 0..width {
+                if !table[pivot_row][j].is_zero() {
+                    table[i][j] = table[i][j].sub(&factor.mul(&table[pivot_row][j])?)?;
+                }
+            }
+            rhs[i] = rhs[i].sub(&factor.mul(&step)?)?;
+        }
+        let factor = cost[entering];
+        for j in 0..width {
+            if !table[pivot_row][j].is_zero() {
+                cost[j] = cost[j].sub(&factor.mul(&table[pivot_row][j])?)?;
+            }
+        }
+        objective = objective.add(&factor.mul(&step)?)?;
+        basis[pivot_row] = entering;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feasible_system() {
+        //x + 2y >= 4, x <= 1
+        assert_eq!(is_feasible(&vec![vec![1, 2], vec![-1, 0]], &vec![4, -1]), Some(true));
+        //2x = 1 only has a fractional solution
+        assert_eq!(is_feasible(&vec![vec![2], vec![-2]], &vec![1, -1]), Some(true));
+        assert_eq!(is_feasible(&vec![], &vec![]), Some(true));
+    }
+
+    #[test]
+    fn infeasible_system() {
+        //x >= 1, x <= 0
+        assert_eq!(is_feasible(&vec![vec![1], vec![-1]], &vec![1, 0]), Some(false));
+        //x + y >= 3, x <= 1, y <= 1
+        assert_eq!(is_feasible(&vec![vec![1, 1], vec![-1, 0], vec![0, -1]], &vec![3, -1, -1]), Some(false));
+        //variables are non-negative: -x >= 1
+        assert_eq!(is_feasible(&vec![vec![-1]], &vec![1]), Some(false));
+    }
+
+    #[test]
+    fn degenerate_system() {
+        //x = y and x + y >= 2, the first two constraints are tight at every vertex
+        let rows = vec![vec![1, -1], vec![-1, 1], vec![1, 1]];
+        assert_eq!(is_feasible(&rows, &vec![0, 0, 2]), Some(true));
+        //x >= y >= z >= x + 1, sums to 0 >= 1
+        let rows = vec![vec![1, -1, 0], vec![0, 1, -1], vec![-1, 0, 1]];
+        assert_eq!(is_feasible(&rows, &vec![0, 0, 1]), Some(false));
+    }
+
+    #[test]
+    fn rational_arithmetic() {
+        let half = Rational::new(2, 4).unwrap();
+        assert_eq!(half, Rational { num: 1, den: 2 });
+        assert_eq!(Rational::new(1, -2), Some(Rational { num: -1, den: 2 }));
+        assert_eq!(half.add(&Rational::new(1, 3).unwrap()), Rational::new(5, 6));
+        assert_eq!(half.div(&Rational::integer(-2)), Rational::new(-1, 4));
+        assert_eq!(Rational::integer(0).div(&Rational::integer(0)), None);
+        assert_eq!(Rational { num: i128::max_value(), den: 1 }.add(&Rational::integer(1)), None);
+    }
+}
@@ -0,0 +1,354 @@
+use std::mem::discriminant;
+
+use query::*;
+use query::Operator::*;
+
+//Note: Rewriting follows the maximal path semantics used by the checker: in a deadlock, EX is
+//false and AX is true for any formula, so EX true and AX false are not constants. All other
+//temporal operators keep constant arguments (EF true = true, EG false = false, ...).
+
+///Rewrite the query to an equivalent normalized one: negations are pushed to the atoms, constants
+///are folded, nested conjunctions/disjunctions are flattened without duplicates and repeated
+///EF/AF/EG/AG are merged. Ids are assigned again, the root has the largest id.
+pub fn simplify(query: Query) -> Query {
+    let mut result = rewrite(query);
+    renumber(&mut result, &mut 0);
+    result
+}
+
+fn node(operator: Operator) -> Query {
+    Query { id: 0, operator: operator }
+}
+
+fn constant(value: bool) -> Query {
+    node(Atom(Proposition::constant(value)))
+}
+
+fn as_constant(query: &Query) -> Option<bool> {
+    match query.operator {
+        Atom(ref proposition) => match proposition.test {
+            Test::Constant(value) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn rewrite(query: Query) -> Query {
+    match query.operator {
+        Atom(proposition) => atom(proposition),
+        Not(inner) => negate(rewrite(*inner)),
+        And(items) => junction(items.into_iter().map(rewrite).collect(), true),
+        Or(items) => junction(items.into_iter().map(rewrite).collect(), false),
+        EF(inner) => idempotent(rewrite(*inner), EF),
+        AF(inner) => idempotent(rewrite(*inner), AF),
+        EG(inner) => idempotent(rewrite(*inner), EG),
+        AG(inner) => idempotent(rewrite(*inner), AG),
+        EX(inner) => next(rewrite(*inner), EX),
+        AX(inner) => next(rewrite(*inner), AX),
+        EU(left, right) => until(rewrite(*left), rewrite(*right), EU),
+        AU(left, right) => until(rewrite(*left), rewrite(*right), AU),
+    }
+}
+
+///Comparisons that don't depend on the marking (i.e. 1 < 2 or p1 <= p1) become constants
+fn atom(proposition: Proposition) -> Query {
+    let independent = proposition.constraints.iter().all(|constraint| {
+        let mut places: Vec<usize> = constraint.coefficients.iter().map(|&(p, _)| p).collect();
+        places.sort();
+        places.dedup();
+        places.iter().all(|&p| {
+            constraint.coefficients.iter().filter(|&&(q, _)| q == p).map(|&(_, c)| c).sum::<i64>() == 0
+        })
+    });
+    if independent {
+        constant(proposition.constraints.iter().all(|c| c.bound <= 0))
+    } else {
+        node(Atom(proposition))
+    }
+}
+
+///Negation of an already rewritten query
+fn negate(query: Query) -> Query {
+    match query.operator {
+        Atom(proposition) => match proposition.negate() {
+            Some(negated) => node(Atom(negated)),
+            None => node(Not(Box::new(node(Atom(proposition))))),
+        },
+        Not(inner) => *inner,
+        And(items) => junction(items.into_iter().map(negate).collect(), false),
+        Or(items) => junction(items.into_iter().map(negate).collect(), true),
+        EF(inner) => idempotent(negate(*inner), AG),
+        AG(inner) => idempotent(negate(*inner), EF),
+        AF(inner) => idempotent(negate(*inner), EG),
+        EG(inner) => idempotent(negate(*inner), AF),
+        EX(inner) => next(negate(*inner), AX),
+        AX(inner) => next(negate(*inner), EX),
+        operator => node(Not(Box::new(node(operator)))),    //until has no dual operator here
+    }
+}
+
+///Flattened conjunction (or disjunction) of rewritten items without constants and duplicates
+fn junction(items: Vec<Query>, conjunction: bool) -> Query {
+    let mut result: Vec<Query> = Vec::new();
+    for item in items {
+        let parts = match item.operator {
+            And(parts) if conjunction => parts,
+            Or(parts) if !conjunction => parts,
+            operator => vec![Query { id: item.id, operator: operator }],
+        };
+        for part in parts {
+            match as_constant(&part) {
+                Some(value) if value == conjunction => continue,
+                Some(_) => return constant(!conjunction),
+                None => {
+                    if !result.iter().any(|r| same(r, &part)) {
+                        result.push(part);
+                    }
+                }
+            }
+        }
+    }
+    match result.len() {
+        0 => constant(conjunction),
+        1 => result.pop().unwrap(),
+        _ => node(if conjunction { And(result) } else { Or(result) }),
+    }
+}
+
+///EF, AF, EG and AG of a constant are the constant, EF EF is EF (and so on)
+fn idempotent(inner: Query, wrap: fn(Box<Query>) -> Operator) -> Query {
+    if as_constant(&inner).is_some() {
+        return inner;
+    }
+    let operator = wrap(Box::new(inner));
+    let repeated = match operator {
+        EF(ref inner) | AF(ref inner) | EG(ref inner) | AG(ref inner) => {
+            discriminant(&operator) == discriminant(&inner.operator)
+        }
+        _ => false,
+    };
+    match operator {
+        EF(inner) | AF(inner) | EG(inner) | AG(inner) if repeated => *inner,
+        operator => node(operator),
+    }
+}
+
+///EX false is false and AX true is true
+fn next(inner: Query, wrap: fn(Box<Query>) -> Operator) -> Query {
+    let operator = wrap(Box::new(inner));
+    let folded = match operator {
+        EX(ref inner) => as_constant(inner) == Some(false),
+        AX(ref inner) => as_constant(inner) == Some(true),
+        _ => false,
+    };
+    match operator {
+        EX(inner) | AX(inner) if folded => *inner,
+        operator => node(operator),
+    }
+}
+
+fn until(left: Query, right: Query, wrap: fn(Box<Query>, Box<Query>) -> Operator) -> Query {
+    match (as_constant(&left), as_constant(&right)) {
+        (_, Some(_)) | (Some(false), _) => right,
+        (Some(true), _) => match wrap(Box::new(left), Box::new(right)) {
+            EU(_, right) => idempotent(*right, EF),
+            AU(_, right) => idempotent(*right, AF),
+            operator => node(operator),
+        },
+        _ => node(wrap(Box::new(left), Box::new(right))),
+    }
+}
+
+///Structural equality ignoring ids
+fn same(a: &Query, b: &Query) -> bool {
+    match (&a.operator, &b.operator) {
+        (&Atom(ref x), &Atom(ref y)) => x == y,
+        (x, y) => {
+            let (x, y) = (x.children(), y.children());
+            discriminant(&a.operator) == discriminant(&b.operator) && x.len() == y.len() &&
+                x.iter().zip(y.iter()).all(|(x, y)| same(x, y))
+        }
+    }
+}
+
+///Children get smaller ids than their parents, the right side of until smaller than the left side
+fn renumber(query: &mut Query, next_id: &mut QueryId) {
+    match query.operator {
+        Atom(_) => {}
+        Not(ref mut inner) | EF(ref mut inner) | AF(ref mut inner) | EG(ref mut inner) |
+        AG(ref mut inner) | EX(ref mut inner) | AX(ref mut inner) => renumber(inner, next_id),
+        And(ref mut items) | Or(ref mut items) => {
+            for item in items.iter_mut() {
+                renumber(item, next_id);
+            }
+        }
+        AU(ref mut left, ref mut right) | EU(ref mut left, ref mut right) => {
+            renumber(right, next_id);
+            renumber(left, next_id);
+        }
+    }
+    query.id = *next_id;
+    *next_id += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///m[p] <= value
+    fn le(p: usize, value: i64) -> Query {
+        node(Atom(Proposition::compare(Expression::tokens(&vec![p]), Comparison::LE, Expression::constant(value))))
+    }
+
+    ///m[p] > value
+    fn gt(p: usize, value: i64) -> Query {
+        node(Atom(Proposition::compare(Expression::tokens(&vec![p]), Comparison::GT, Expression::constant(value))))
+    }
+
+    ///Transition t with a single input arc from place t
+    fn fireable(t: usize) -> Query {
+        node(Atom(Proposition {
+            places: vec![t],
+            transitions: vec![t],
+            constraints: vec![Constraint { coefficients: vec![(t, 1)], bound: 1 }],
+            test: Test::Fireable(t),
+        }))
+    }
+
+    fn unary(wrap: fn(Box<Query>) -> Operator, inner: Query) -> Query {
+        node(wrap(Box::new(inner)))
+    }
+
+    fn binary(wrap: fn(Box<Query>, Box<Query>) -> Operator, left: Query, right: Query) -> Query {
+        node(wrap(Box::new(left), Box::new(right)))
+    }
+
+    fn assert_simplifies(query: Query, expected: Query) {
+        let result = simplify(query);
+        assert!(same(&result, &expected), "got {:?}, expected {:?}", result, expected);
+    }
+
+    #[test]
+    fn independent_comparisons_are_constants() {
+        let compare = |left: Expression, comparison, right: Expression| node(Atom(Proposition::compare(left, comparison, right)));
+        assert_simplifies(compare(Expression::constant(1), Comparison::LT, Expression::constant(2)), constant(true));
+        assert_simplifies(compare(Expression::constant(2), Comparison::LE, Expression::constant(1)), constant(false));
+        let p0 = Expression::tokens(&vec![0]);
+        assert_simplifies(compare(p0.clone(), Comparison::LE, p0.clone()), constant(true));
+        assert_simplifies(compare(p0.clone(), Comparison::LT, p0.clone()), constant(false));
+        assert_simplifies(le(0, 1), le(0, 1));
+    }
+
+    #[test]
+    fn negations_are_pushed_to_atoms() {
+        assert_simplifies(unary(Not, le(0, 1)), gt(0, 1));
+        assert_simplifies(unary(Not, unary(Not, le(0, 1))), le(0, 1));
+        assert_simplifies(unary(Not, constant(true)), constant(false));
+        //fireability has no negated atom
+        assert_simplifies(unary(Not, unary(Not, fireable(0))), fireable(0));
+        assert_simplifies(unary(Not, fireable(0)), unary(Not, fireable(0)));
+        assert_simplifies(unary(Not, node(And(vec![le(0, 1), le(1, 2)]))), node(Or(vec![gt(0, 1), gt(1, 2)])));
+        assert_simplifies(unary(Not, node(Or(vec![le(0, 1), le(1, 2)]))), node(And(vec![gt(0, 1), gt(1, 2)])));
+    }
+
+    #[test]
+    fn negations_swap_path_quantifiers() {
+        assert_simplifies(unary(Not, unary(EF, le(0, 1))), unary(AG, gt(0, 1)));
+        assert_simplifies(unary(Not, unary(AG, le(0, 1))), unary(EF, gt(0, 1)));
+        assert_simplifies(unary(Not, unary(AF, le(0, 1))), unary(EG, gt(0, 1)));
+        assert_simplifies(unary(Not, unary(EG, le(0, 1))), unary(AF, gt(0, 1)));
+        assert_simplifies(unary(Not, unary(EX, le(0, 1))), unary(AX, gt(0, 1)));
+        assert_simplifies(unary(Not, unary(AX, le(0, 1))), unary(EX, gt(0, 1)));
+    }
+
+    #[test]
+    fn negated_until_is_kept() {
+        let eu = || binary(EU, le(0, 1), le(1, 1));
+        let au = || binary(AU, le(0, 1), le(1, 1));
+        assert_simplifies(unary(Not, eu()), unary(Not, eu()));
+        assert_simplifies(unary(Not, au()), unary(Not, au()));
+        assert_simplifies(unary(Not, unary(Not, au())), au());
+    }
+
+    #[test]
+    fn junctions_are_flattened() {
+        let nested = node(And(vec![le(0, 1), node(And(vec![le(1, 1), le(0, 1)])), le(2, 1)]));
+        assert_simplifies(nested, node(And(vec![le(0, 1), le(1, 1), le(2, 1)])));
+        let nested = node(Or(vec![node(Or(vec![le(0, 1), le(1, 1)])), le(1, 1)]));
+        assert_simplifies(nested, node(Or(vec![le(0, 1), le(1, 1)])));
+        //a conjunction inside of a disjunction stays
+        let mixed = node(Or(vec![node(And(vec![le(0, 1), le(1, 1)])), le(2, 1)]));
+        assert_simplifies(mixed, node(Or(vec![node(And(vec![le(0, 1), le(1, 1)])), le(2, 1)])));
+        assert_simplifies(node(And(vec![le(0, 1), le(0, 1)])), le(0, 1));
+    }
+
+    #[test]
+    fn junctions_fold_constants() {
+        assert_simplifies(node(And(vec![constant(true), le(0, 1)])), le(0, 1));
+        assert_simplifies(node(And(vec![constant(false), le(0, 1)])), constant(false));
+        assert_simplifies(node(Or(vec![constant(false), le(0, 1)])), le(0, 1));
+        assert_simplifies(node(Or(vec![le(0, 1), constant(true)])), constant(true));
+        assert_simplifies(node(And(vec![constant(true), constant(true)])), constant(true));
+        assert_simplifies(node(Or(vec![constant(false)])), constant(false));
+    }
+
+    #[test]
+    fn repeated_operators_are_merged() {
+        for &wrap in &[EF as fn(Box<Query>) -> Operator, AF, EG, AG] {
+            assert_simplifies(unary(wrap, unary(wrap, le(0, 1))), unary(wrap, le(0, 1)));
+        }
+        assert_simplifies(unary(EF, unary(AG, le(0, 1))), unary(EF, unary(AG, le(0, 1))));
+        assert_simplifies(unary(AG, unary(AF, le(0, 1))), unary(AG, unary(AF, le(0, 1))));
+    }
+
+    #[test]
+    fn temporal_operators_keep_constants() {
+        for &wrap in &[EF as fn(Box<Query>) -> Operator, AF, EG, AG] {
+            assert_simplifies(unary(wrap, constant(true)), constant(true));
+            assert_simplifies(unary(wrap, constant(false)), constant(false));
+        }
+    }
+
+    #[test]
+    fn next_folds_constants_that_hold_in_deadlocks() {
+        assert_simplifies(unary(EX, constant(false)), constant(false));
+        assert_simplifies(unary(AX, constant(true)), constant(true));
+        //EX true is false and AX false is true in a deadlock
+        assert_simplifies(unary(EX, constant(true)), unary(EX, constant(true)));
+        assert_simplifies(unary(AX, constant(false)), unary(AX, constant(false)));
+        assert_simplifies(unary(EX, unary(EX, le(0, 1))), unary(EX, unary(EX, le(0, 1))));
+    }
+
+    #[test]
+    fn until_with_constants() {
+        for &wrap in &[EU as fn(Box<Query>, Box<Query>) -> Operator, AU] {
+            assert_simplifies(binary(wrap, le(0, 1), constant(true)), constant(true));
+            assert_simplifies(binary(wrap, le(0, 1), constant(false)), constant(false));
+            assert_simplifies(binary(wrap, constant(false), le(1, 1)), le(1, 1));
+            assert_simplifies(binary(wrap, le(0, 1), le(1, 1)), binary(wrap, le(0, 1), le(1, 1)));
+        }
+        assert_simplifies(binary(EU, constant(true), le(1, 1)), unary(EF, le(1, 1)));
+        assert_simplifies(binary(AU, constant(true), le(1, 1)), unary(AF, le(1, 1)));
+        assert_simplifies(binary(EU, constant(true), unary(EF, le(1, 1))), unary(EF, le(1, 1)));
+        assert_simplifies(binary(AU, constant(true), unary(AF, le(1, 1))), unary(AF, le(1, 1)));
+    }
+
+    #[test]
+    fn ids_grow_towards_the_root() {
+        let query = simplify(node(And(vec![binary(EU, le(0, 1), le(1, 1)), unary(EF, le(2, 1))])));
+        assert_eq!(query.id, 5);
+        match query.operator {
+            And(ref items) => {
+                assert_eq!(items[0].id, 2);
+                assert_eq!(items[1].id, 4);
+                match items[0].operator {
+                    EU(ref left, ref right) => assert_eq!((left.id, right.id), (1, 0)),
+                    _ => panic!("expected until, got {:?}", items[0]),
+                }
+                assert_eq!(items[1].operator.children()[0].id, 3);
+            }
+            _ => panic!("expected conjunction, got {:?}", query),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use petri_net::*;
+use query::*;
+use query::Operator::*;
+use simplex::is_feasible;
+use invariants::incidence;
+
+//Note: Every reachable marking m satisfies the state equation m = m0 + C * x, where C is the
+//incidence matrix and x >= 0 counts how many times each transition was fired. If no x (not even
+//a real one) gives a marking satisfying the proposition, no reachable marking satisfies it.
+//State formulas are turned into disjunctions of cubes (conjunctions of linear constraints) and
+//each cube is checked separately. This only ever proves unreachability, so it can decide EF
+//queries that are false and AG queries that are true.
+
+///Conjunction of linear constraints
+type Cube = Vec<Constraint>;
+
+///Formulas that would need more cubes are over-approximated
+const MAX_CUBES: usize = 64;
+
+///Decide a (possibly negated) EF/AG query using the state equation.
+///Returns None if the state equation doesn't prove anything.
+pub fn check(net: &PetriNet, query: &Query) -> Option<bool> {
+    match query.operator {
+        Not(ref inner) => check(net, inner).map(|value| !value),
+        EF(ref inner) if inner.is_state_formula() && is_unreachable(net, inner, false) => Some(false),
+        AG(ref inner) if inner.is_state_formula() && is_unreachable(net, inner, true) => Some(true),
+        _ => None,
+    }
+}
+
+///True if no marking satisfying the state formula (or its negation) is reachable
+pub fn is_unreachable(net: &PetriNet, formula: &Query, negated: bool) -> bool {
+    let places = net.initial_marking.len();
+    let incidence = incidence(net);
+    //markings have to be non-negative: C[p] * x >= -m0[p]
+    let mut rows = Vec::new();
+    let mut bounds = Vec::new();
+    for p in 0..places {
+        if incidence[p].iter().any(|&c| c != 0) {
+            rows.push(incidence[p].clone());
+            bounds.push(-(net.initial_marking[p] as i64));
+        }
+    }
+    cubes(formula, negated).iter().all(|cube| {
+        let mut rows = rows.clone();
+        let mut bounds = bounds.clone();
+        for constraint in cube {
+            //sum c[p] * (m0[p] + C[p] * x) >= bound
+            let mut row = vec![0i64; net.matrix.len()];
+            let mut bound = constraint.bound;
+            for &(p, c) in &constraint.coefficients {
+                bound -= c * net.initial_marking[p] as i64;
+                for t in 0..net.matrix.len() {
+                    row[t] += c * incidence[p][t];
+                }
+            }
+            if row.iter().all(|&a| a == 0) {
+                if bound > 0 {
+                    return true;    //violated by every marking
+                }
+            } else {
+                rows.push(row);
+                bounds.push(bound);
+            }
+        }
+        is_feasible(&rows, &bounds) == Some(false)
+    })
+}
+
+///Cubes whose disjunction is implied by the state formula (or its negation)
+fn cubes(formula: &Query, negated: bool) -> Vec<Cube> {
+    match formula.operator {
+        Atom(ref proposition) if negated => {
+            proposition.constraints.iter().map(|c| vec![c.negate()]).collect()
+        }
+        Atom(ref proposition) => vec![proposition.constraints.clone()],
+        Not(ref inner) => cubes(inner, !negated),
+        And(ref items) if !negated => conjunction(items, negated),
+        Or(ref items) if negated => conjunction(items, negated),
+        And(ref items) | Or(ref items) => {
+            let mut result = Vec::new();
+            for item in items {
+                result.extend(cubes(item, negated));
+            }
+            if result.len() > MAX_CUBES {
+                vec![vec![]]
+            } else {
+                result
+            }
+        }
+        _ => vec![vec![]],
+    }
+}
+
+fn conjunction(items: &Vec<Query>, negated: bool) -> Vec<Cube> {
+    let mut result: Vec<Cube> = vec![vec![]];
+    for item in items {
+        let item_cubes = cubes(item, negated);
+        //leaving out a conjunct only makes the formula weaker
+        if result.len() * item_cubes.len() > MAX_CUBES {
+            continue;
+        }
+        let mut product = Vec::new();
+        for cube in &result {
+            for other in &item_cubes {
+                let mut joined = cube.clone();
+                joined.extend(other.iter().cloned());
+                product.push(joined);
+            }
+        }
+        result = product;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formula::{Formula, Integer};
+
+    fn net(places: usize, initial_marking: Marking, matrix: Vec<(Vec<(usize, u32)>, Vec<(usize, u32)>)>) -> PetriNet {
+        PetriNet {
+            places: (0..places).map(|p| (format!("p{}", p), p)).collect(),
+            transitions: (0..matrix.len()).map(|t| (format!("t{}", t), t)).collect(),
+            initial_marking: initial_marking,
+            matrix: matrix,
+        }
+    }
+
+    ///Two processes (idle p0/p2, critical p1/p3) sharing a lock p4. Transitions t0/t2 enter and
+    ///t1/t3 leave the critical section.
+    fn mutex() -> PetriNet {
+        net(5, vec![1, 0, 1, 0, 1], vec![
+            (vec![(0, 1), (4, 1)], vec![(1, 1)]),
+            (vec![(1, 1)], vec![(0, 1), (4, 1)]),
+            (vec![(2, 1), (4, 1)], vec![(3, 1)]),
+            (vec![(3, 1)], vec![(2, 1), (4, 1)]),
+        ])
+    }
+
+    fn compare(places: &[usize], comparison: Comparison, value: i64) -> Formula {
+        let places = places.iter().map(|p| format!("p{}", p)).collect();
+        Formula::Compare(Integer::Tokens(places), comparison, Integer::Constant(value))
+    }
+
+    fn check_formula(net: &PetriNet, formula: Formula) -> Option<bool> {
+        check(net, &Query::compile(&formula, net).unwrap())
+    }
+
+    #[test]
+    fn mutual_exclusion_is_proved() {
+        let net = mutex();
+        let both = Formula::And(vec![compare(&[1], Comparison::GE, 1), compare(&[3], Comparison::GE, 1)]);
+        assert_eq!(check_formula(&net, Formula::EF(Box::new(both.clone()))), Some(false));
+        let never = Formula::AG(Box::new(Formula::Not(Box::new(both.clone()))));
+        assert_eq!(check_formula(&net, never), Some(true));
+        let negated = Formula::Not(Box::new(Formula::EF(Box::new(both))));
+        assert_eq!(check_formula(&net, negated), Some(true));
+    }
+
+    #[test]
+    fn reachable_marking_is_not_refuted() {
+        let net = mutex();
+        assert_eq!(check_formula(&net, Formula::EF(Box::new(compare(&[1], Comparison::GE, 1)))), None);
+        assert_eq!(check_formula(&net, Formula::AG(Box::new(compare(&[4], Comparison::GE, 1)))), None);
+        //only reachability queries are decided
+        let next = Formula::EX(Box::new(compare(&[1, 3], Comparison::GE, 2)));
+        assert_eq!(check_formula(&net, next), None);
+    }
+
+    #[test]
+    fn spurious_solution_is_not_refuted() {
+        //t0 reads p0 which is never marked, the state equation still lets it fire
+        let net = net(2, vec![0, 0], vec![(vec![(0, 1)], vec![(0, 1), (1, 1)])]);
+        assert_eq!(check_formula(&net, Formula::EF(Box::new(compare(&[1], Comparison::GE, 1)))), None);
+        assert_eq!(check_formula(&net, Formula::EF(Box::new(compare(&[0], Comparison::GE, 1)))), Some(false));
+    }
+}
@@ -1,59 +1,535 @@
-use std::collections::HashMap;
+use std::mem;
+use std::cmp::max;
+use std::cell::Cell;
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::hash::{Hasher, BuildHasher};
 use twox_hash::RandomXxHashBuilder;
+use memmap::{MmapMut, MmapOptions};
 use graph::Value;
-use typed_arena::Arena;
 
 use petri_net::*;
 
 pub type MarkingId = usize;
 
-pub struct MarkingSet<'a> {
-    storage: &'a Arena<Marking>,
-    markings: Vec<&'a Marking>,
-    id_map: HashMap<&'a Marking, MarkingId, RandomXxHashBuilder>
+//Note: Markings are stored encoded in one byte vector, every token count is written as a variable
+//width integer (7 bits per byte, highest bit set if more bytes follow). Most places hold only a few
+//tokens, so a marking usually takes one byte per place instead of four. Markings are looked up
+//through an open addressing hash table of their ids, which compares the encoded bytes directly.
+
+const MIN_TABLE_SIZE: usize = 1024;
+
+pub struct MarkingSet {
+    places: usize,
+    data: Vec<u8>,
+    //end of each marking in data, marking starts where the previous one ends
+    ends: Vec<usize>,
+    //marking id + 1 for each slot, zero for an empty slot
+    table: Vec<usize>,
+    hasher: RandomXxHashBuilder,
+    //encoding of the marking being inserted
+    buffer: Vec<u8>,
+    //hash of every marking, only kept when encodings can't be read cheaply (eviction or spill)
+    hashes: Vec<u64>,
+    eviction: Option<Eviction>,
+    spill: Option<Spill>,
+}
+
+//Note: Once the memory of the set exceeds the budget, the least visited markings are evicted - only
+//their encoding is dropped, ids stay valid. Every marking remembers the marking and transition it
+//was discovered from, so an evicted marking is recomputed by firing the transition again. A marking
+//is only evicted while its source is still stored, so every eviction round makes the chains of
+//recomputed transitions at most one step longer. Assignments and cached successors are indexed by
+//the stable ids, so they stay valid and don't have to be recomputed. Assignments can't be evicted
+//(running searches use them to mark visited markings), they are packed to two bits per marking.
+
+//Note: With a spill file, the encoded markings are appended to the file whenever the data in memory
+//grows over the threshold, so the file holds the beginning of the data and the vector the rest.
+//Markings in the file are read back when needed. The hash table, marking ends and hashes stay in
+//memory (about 32 bytes per marking), hashes are compared before a marking is read and the table
+//grows without reading any marking. Successor caches and assignments spill to their own files.
+
+///Directory for spill files and the number of bytes each store keeps in memory before it moves its
+///data there
+#[derive(Debug, Clone)]
+pub struct SpillConfig {
+    pub directory: PathBuf,
+    pub threshold: usize,
+}
+
+//used to give every spill file of this process a different name
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+//Temporary file holding data moved out of memory, created on the first write and removed when
+//dropped. The file is mapped to memory, so spilled data is read and overwritten without a system
+//call per lookup and the kernel decides which pages stay resident. The file is grown ahead of the
+//data (doubling its length) and mapped again whenever the data doesn't fit. I/O errors are
+//remembered instead of returned, so that lookups keep their signatures. Data that can't be read is
+//zeroed and searches are stopped by Graph once a failure is seen.
+#[derive(Debug)]
+struct SpillFile {
+    directory: PathBuf,
+    file: Option<(File, PathBuf)>,
+    //mapping of the whole file, the file can be longer than the data in it
+    map: Option<MmapMut>,
+    //number of bytes of data in the file
+    bytes: usize,
+    failed: Cell<bool>,
 }
 
-impl <'a> MarkingSet<'a> {
+impl SpillFile {
 
-    pub fn new<'b>(arena: &'b Arena<Marking>) -> MarkingSet<'b> {
-        MarkingSet { storage: arena, markings: Vec::new(), id_map: Default::default() }
+    fn new(directory: &Path) -> SpillFile {
+        SpillFile { directory: directory.to_path_buf(), file: None, map: None, bytes: 0, failed: Cell::new(false) }
+    }
+
+    fn open(&mut self) -> io::Result<&File> {
+        if self.file.is_none() {
+            let name = format!("spill-{}-{}.bin", process::id(), SPILL_FILES.fetch_add(1, Ordering::SeqCst));
+            let path = self.directory.join(name);
+            let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+            self.file = Some((file, path));
+        }
+        Ok(&self.file.as_ref().unwrap().0)
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.as_ref().map(|map| map.len()).unwrap_or(0)
+    }
+
+    ///Grow the file to hold at least given number of bytes and map it again
+    fn reserve(&mut self, bytes: usize) -> io::Result<()> {
+        let capacity = max(bytes, 2 * self.capacity());
+        self.map = None;
+        let map = {
+            let file = self.open()?;
+            file.set_len(capacity as u64)?;
+            unsafe { MmapOptions::new().map_mut(file)? }
+        };
+        self.map = Some(map);
+        Ok(())
+    }
+
+    ///Append data at the end of the file, returns false if it wasn't written
+    fn append(&mut self, data: &[u8]) -> bool {
+        let end = self.bytes + data.len();
+        if end > self.capacity() && self.reserve(end).is_err() {
+            self.failed.set(true);
+            return false;
+        }
+        if let Some(ref mut map) = self.map {
+            map[self.bytes..end].copy_from_slice(data);
+        }
+        self.bytes = end;
+        true
+    }
+
+    ///Data between given bytes of the file
+    fn slice(&self, start: usize, end: usize) -> Cow<[u8]> {
+        match self.map {
+            Some(ref map) if end <= self.bytes => Cow::Borrowed(&map[start..end]),
+            _ => {
+                self.failed.set(true);
+                Cow::Owned(vec![0; end - start])
+            }
+        }
+    }
+
+    ///Read data starting at given byte of the file
+    fn read_at(&self, offset: usize, data: &mut [u8]) {
+        let length = data.len();
+        data.copy_from_slice(&self.slice(offset, offset + length));
+    }
+
+    ///Overwrite data starting at given byte of the file
+    fn write_at(&mut self, offset: usize, data: &[u8]) {
+        match self.map {
+            Some(ref mut map) if offset + data.len() <= self.bytes => {
+                map[offset..offset + data.len()].copy_from_slice(data);
+            }
+            _ => self.failed.set(true),
+        }
+    }
+
+    fn failed(&self) -> bool {
+        self.failed.get()
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Some((_, ref path)) = self.file {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+struct Spill {
+    file: SpillFile,
+    threshold: usize,
+}
+
+//Bookkeeping needed to evict markings and recompute them later
+struct Eviction {
+    budget: usize,
+    //size of data that triggers next eviction round
+    limit: usize,
+    matrix: Vec<(Vec<(usize, u32)>, Vec<(usize, u32)>)>,
+    //source marking and transition, None for markings that can't be evicted (initial marking)
+    origins: Vec<Option<(MarkingId, usize)>>,
+    //how many times was the marking found again (halved every eviction round)
+    visits: Vec<u8>,
+    evicted: Vec<bool>,
+}
+
+impl MarkingSet {
+
+    pub fn new() -> MarkingSet {
+        MarkingSet {
+            places: 0, data: Vec::new(), ends: Vec::new(), table: Vec::new(),
+            hasher: Default::default(), buffer: Vec::new(), hashes: Vec::new(), eviction: None, spill: None,
+        }
+    }
+
+    ///Marking set which moves encoded markings to a file in the spill directory once they take
+    ///more than threshold bytes of memory. The file is created right away, so that an unusable
+    ///directory is reported here, and removed when the set is dropped.
+    pub fn with_spill(config: &SpillConfig) -> io::Result<MarkingSet> {
+        let mut file = SpillFile::new(&config.directory);
+        file.open()?;
+        let mut set = MarkingSet::new();
+        set.spill = Some(Spill { file: file, threshold: config.threshold });
+        Ok(set)
+    }
+
+    ///Marking set which keeps its memory (see memory) within the budget in bytes by evicting the
+    ///least visited markings. Evicted markings are recomputed using transitions of the net.
+    ///Only encodings are evicted, the bookkeeping of about 60 bytes per marking is kept, so the
+    ///budget can't hold once the bookkeeping alone exceeds it.
+    pub fn with_budget(net: &PetriNet, budget: usize) -> MarkingSet {
+        let mut set = MarkingSet::new();
+        set.eviction = Some(Eviction {
+            budget: budget, limit: budget, matrix: net.matrix.clone(),
+            origins: Vec::new(), visits: Vec::new(), evicted: Vec::new(),
+        });
+        set
     }
 
     pub fn insert(&mut self, marking: &Marking) -> MarkingId {
-        if self.id_map.contains_key(marking) {
-            self.id_map[marking]
-        } else {
-            let marking_ref = self.storage.alloc(marking.clone());
-            let new_id = self.markings.len();
-            if new_id % 100000 == 0 {
-                println!("Markings: {:?}", new_id);
+        self.insert_from(marking, None)
+    }
+
+    ///Insert marking obtained by firing the transition in the source marking
+    pub fn insert_successor(&mut self, marking: &Marking, source: MarkingId, transition: usize) -> MarkingId {
+        self.insert_from(marking, Some((source, transition)))
+    }
+
+    fn insert_from(&mut self, marking: &Marking, origin: Option<(MarkingId, usize)>) -> MarkingId {
+        let mut buffer = mem::replace(&mut self.buffer, Vec::new());
+        encode(marking, &mut buffer);
+        if 2 * (self.ends.len() + 1) > self.table.len() {
+            self.grow();
+        }
+        let mask = self.table.len() - 1;
+        let hash = self.hash(&buffer);
+        let mut slot = (hash as usize) & mask;
+        let id = loop {
+            match self.table[slot] {
+                0 => {
+                    let new_id = self.ends.len();
+                    if new_id % 100000 == 0 {
+                        eprintln!("Markings: {:?}", new_id);
+                    }
+                    self.places = marking.len();
+                    self.data.extend_from_slice(&buffer);
+                    let spilled = self.spill.as_ref().map(|spill| spill.file.bytes).unwrap_or(0);
+                    self.ends.push(spilled + self.data.len());
+                    self.table[slot] = new_id + 1;
+                    if self.keeps_hashes() {
+                        self.hashes.push(hash);
+                    }
+                    if let Some(ref mut spill) = self.spill {
+                        //data stays in memory if it can't be written
+                        if self.data.len() > spill.threshold && spill.file.append(&self.data) {
+                            self.data.clear();
+                        }
+                    }
+                    if let Some(ref mut eviction) = self.eviction {
+                        eviction.origins.push(origin);
+                        eviction.visits.push(0);
+                        eviction.evicted.push(false);
+                    }
+                    break new_id;
+                }
+                stored if self.is_stored(stored - 1, hash, &buffer) => {
+                    if let Some(ref mut eviction) = self.eviction {
+                        let ref mut visits = eviction.visits[stored - 1];
+                        *visits = visits.saturating_add(1);
+                    }
+                    break stored - 1;
+                }
+                _ => slot = (slot + 1) & mask,
+            }
+        };
+        self.buffer = buffer;
+        if self.eviction.as_ref().map(|e| self.memory() > e.limit).unwrap_or(false) {
+            self.evict();
+        }
+        id
+    }
+
+    ///Decode marking with given id
+    pub fn get(&self, id: MarkingId) -> Marking {
+        let mut marking = Vec::with_capacity(self.places);
+        self.get_into(id, &mut marking);
+        marking
+    }
+
+    ///Decode marking with given id into the given buffer, so that hot paths don't allocate
+    pub fn get_into(&self, id: MarkingId, marking: &mut Marking) {
+        marking.clear();
+        if let Some(ref eviction) = self.eviction {
+            if eviction.evicted[id] {
+                return self.recompute(eviction, id, marking);
+            }
+        }
+        let mut value = 0;
+        let mut shift = 0;
+        for &byte in self.encoded(id).iter() {
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                marking.push(value);
+                value = 0;
+                shift = 0;
+            } else {
+                shift += 7;
+            }
+        }
+    }
+
+    ///Number of markings stored so far
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    ///Rough estimate of memory used by the stored markings in bytes (spilled markings don't count)
+    pub fn memory(&self) -> usize {
+        let eviction = self.eviction.as_ref().map(|e| {
+            e.origins.capacity() * mem::size_of::<Option<(MarkingId, usize)>>() + e.visits.capacity() + e.evicted.capacity()
+        }).unwrap_or(0);
+        self.data.capacity() + (self.ends.capacity() + self.table.capacity()) * mem::size_of::<usize>() +
+            self.hashes.capacity() * mem::size_of::<u64>() + eviction
+    }
+
+    ///Budget given to with_budget, if any
+    pub fn budget(&self) -> Option<usize> {
+        self.eviction.as_ref().map(|e| e.budget)
+    }
+
+    ///True once reading or writing the spill file failed, markings read since then are wrong
+    pub fn io_failed(&self) -> bool {
+        self.spill.as_ref().map(|spill| spill.file.failed()).unwrap_or(false)
+    }
+
+    fn keeps_hashes(&self) -> bool {
+        self.eviction.is_some() || self.spill.is_some()
+    }
+
+    fn start(&self, id: MarkingId) -> usize {
+        if id == 0 { 0 } else { self.ends[id - 1] }
+    }
+
+    fn encoded(&self, id: MarkingId) -> Cow<[u8]> {
+        let (start, end) = (self.start(id), self.ends[id]);
+        match self.spill {
+            //the whole data was always moved at once, so a marking is either in the file or in memory
+            Some(ref spill) if start < spill.file.bytes => spill.file.slice(start, end),
+            Some(ref spill) => Cow::Borrowed(&self.data[start - spill.file.bytes..end - spill.file.bytes]),
+            None => Cow::Borrowed(&self.data[start..end]),
+        }
+    }
+
+    fn is_stored(&self, id: MarkingId, hash: u64, encoded: &[u8]) -> bool {
+        if self.keeps_hashes() && self.hashes[id] != hash {
+            return false;
+        }
+        match self.eviction {
+            Some(ref eviction) if eviction.evicted[id] => {
+                let mut buffer = Vec::with_capacity(encoded.len());
+                encode(&self.get(id), &mut buffer);
+                &buffer[..] == encoded
             }
-            self.markings.push(marking_ref);
-            self.id_map.insert(marking_ref, new_id);
-            new_id
+            _ => &self.encoded(id)[..] == encoded,
         }
     }
 
-    pub fn get(&self, id: MarkingId) -> &Marking {
-        &self.markings[id]
+    fn hash(&self, bytes: &[u8]) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    fn grow(&mut self) {
+        let size = if self.table.is_empty() { MIN_TABLE_SIZE } else { 2 * self.table.len() };
+        let mut table = vec![0; size];
+        for id in 0..self.ends.len() {
+            let hash = if self.keeps_hashes() { self.hashes[id] } else { self.hash(&self.encoded(id)) };
+            let mut slot = (hash as usize) & (size - 1);
+            while table[slot] != 0 {
+                slot = (slot + 1) & (size - 1);
+            }
+            table[slot] = id + 1;
+        }
+        self.table = table;
     }
 
+    //follow the origins back to a stored marking and fire the transitions again
+    fn recompute(&self, eviction: &Eviction, id: MarkingId, marking: &mut Marking) {
+        let mut transitions = Vec::new();
+        let mut current = id;
+        while eviction.evicted[current] {
+            let (source, transition) = eviction.origins[current].unwrap();
+            transitions.push(transition);
+            current = source;
+        }
+        self.get_into(current, marking);
+        for t in transitions.into_iter().rev() {
+            let (ref pre, ref post) = eviction.matrix[t];
+            for &(p, w) in pre {
+                marking[p] -= w;
+            }
+            for &(p, w) in post {
+                marking[p] += w;
+            }
+        }
+    }
+
+    ///Drop encodings of the least visited markings until about half of the data is free, or more
+    ///if that isn't enough to get within the budget
+    fn evict(&mut self) {
+        let budget = self.eviction.as_ref().unwrap().budget;
+        let goal = max(self.data.len() / 2, self.memory().saturating_sub(budget));
+        let mut eviction = self.eviction.take().unwrap();
+        let mut bytes_by_visits = vec![0; 256];
+        for id in 0..self.ends.len() {
+            if !eviction.evicted[id] && eviction.origins[id].is_some() {
+                bytes_by_visits[eviction.visits[id] as usize] += self.ends[id] - self.start(id);
+            }
+        }
+        let mut threshold = 0;
+        let mut freed = 0;
+        while threshold < 255 && freed + bytes_by_visits[threshold] < goal {
+            freed += bytes_by_visits[threshold];
+            threshold += 1;
+        }
+        let mut data = Vec::with_capacity(self.data.len() / 2);
+        let mut start = 0;
+        for id in 0..self.ends.len() {
+            let end = self.ends[id];
+            if !eviction.evicted[id] {
+                let evict = match eviction.origins[id] {
+                    Some((source, _)) => !eviction.evicted[source] && eviction.visits[id] as usize <= threshold,
+                    None => false,
+                };
+                if evict {
+                    eviction.evicted[id] = true;
+                } else {
+                    data.extend_from_slice(&self.data[start..end]);
+                }
+            }
+            //evicted markings are left with an empty encoding
+            self.ends[id] = data.len();
+            eviction.visits[id] /= 2;
+            start = end;
+        }
+        self.data = data;
+        self.eviction = Some(eviction);
+        //the bookkeeping can't be evicted, don't evict again before the set grows by half of the budget
+        let limit = max(budget, self.memory() + budget / 2);
+        self.eviction.as_mut().unwrap().limit = limit;
+    }
+
+}
+
+fn encode(marking: &Marking, buffer: &mut Vec<u8>) {
+    buffer.clear();
+    for &tokens in marking {
+        let mut value = tokens;
+        while value >= 0x80 {
+            buffer.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        buffer.push(value as u8);
+    }
+}
+
+///Write marking id as 8 bytes (little endian)
+fn encode_id(id: MarkingId, buffer: &mut Vec<u8>) {
+    for i in 0..8 {
+        buffer.push((id as u64 >> (8 * i)) as u8);
+    }
+}
+
+fn decode_id(bytes: &[u8]) -> MarkingId {
+    bytes.iter().rev().fold(0, |id, &byte| (id << 8) | byte as u64) as MarkingId
 }
 
+//Note: With spilling, complete successor lists (every transition of the marking was tried) are
+//moved to a file once the lists in memory take more than the threshold. Lists that are still being
+//filled stay in memory, moved lists only keep their position in the file.
+
 ///Used to store previously computed successors
 pub struct SuccessorCache {
     successors: Vec<Vec<MarkingId>>,
     next_transition: Vec<usize>,
+    spill: Option<SuccessorSpill>,
+}
+
+struct SuccessorSpill {
+    file: SpillFile,
+    threshold: usize,
+    transitions: usize,
+    //bytes taken by successor lists in memory
+    in_memory: usize,
+    //first byte and length of every moved list, (0, 0) for lists in memory
+    moved: Vec<(usize, usize)>,
 }
 
 impl SuccessorCache {
 
     pub fn new() -> SuccessorCache {
-        SuccessorCache { successors: Vec::new(), next_transition: Vec::new() }
+        SuccessorCache { successors: Vec::new(), next_transition: Vec::new(), spill: None }
+    }
+
+    ///Cache which moves complete successor lists of a net with given number of transitions to a file
+    ///in the spill directory
+    pub fn with_spill(config: &SpillConfig, transitions: usize) -> SuccessorCache {
+        let mut cache = SuccessorCache::new();
+        cache.spill = Some(SuccessorSpill {
+            file: SpillFile::new(&config.directory), threshold: config.threshold,
+            transitions: transitions, in_memory: 0, moved: Vec::new(),
+        });
+        cache
     }
 
     ///Get successor with given index assuming that successor is cached.
     pub fn get(&self, marking: MarkingId, index: usize) -> Option<MarkingId> {
+        if let Some(ref spill) = self.spill {
+            if let Some(&(start, length)) = spill.moved.get(marking) {
+                if length > 0 {
+                    if index >= length {
+                        return None;
+                    }
+                    let mut bytes = [0; 8];
+                    spill.file.read_at(start + 8 * index, &mut bytes);
+                    return Some(decode_id(&bytes));
+                }
+            }
+        }
         self.successors.get(marking).and_then(|v| v.get(index)).map(|s| s.clone())
     }
 
@@ -73,33 +549,131 @@ impl SuccessorCache {
             self.successors.resize(marking + 1, Vec::new());
         }
         self.successors[marking].push(successor);
+        let full = match self.spill {
+            Some(ref mut spill) => {
+                spill.in_memory += mem::size_of::<MarkingId>();
+                spill.in_memory > spill.threshold
+            }
+            None => false,
+        };
+        if full {
+            self.move_complete();
+        }
+    }
+
+    ///True once reading or writing the spill file failed, successors read since then are wrong
+    pub fn io_failed(&self) -> bool {
+        self.spill.as_ref().map(|spill| spill.file.failed()).unwrap_or(false)
+    }
+
+    fn move_complete(&mut self) {
+        let spill = self.spill.as_mut().unwrap();
+        let mut bytes = Vec::new();
+        let mut lists = Vec::new();
+        for (marking, successors) in self.successors.iter().enumerate() {
+            let complete = self.next_transition.get(marking).map(|&t| t > spill.transitions).unwrap_or(false);
+            if complete && !successors.is_empty() {
+                lists.push((marking, spill.file.bytes + bytes.len(), successors.len()));
+                for &id in successors {
+                    encode_id(id, &mut bytes);
+                }
+            }
+        }
+        //lists stay in memory if they can't be written
+        if !spill.file.append(&bytes) {
+            return;
+        }
+        for (marking, start, length) in lists {
+            if marking >= spill.moved.len() {
+                spill.moved.resize(marking + 1, (0, 0));
+            }
+            spill.moved[marking] = (start, length);
+            self.successors[marking] = Vec::new();
+            spill.in_memory -= length * mem::size_of::<MarkingId>();
+        }
     }
 
 }
 
-#[derive(Debug, Clone)]
+///Values of a subquery in markings, packed to two bits per marking (zero is Unknown). With spilling,
+///the packed values are moved to a file like encoded markings: the file holds the values of the
+///first markings and the vector the rest.
+#[derive(Debug)]
 pub struct AssignmentSet {
-    assignment: Vec<Value>
+    assignment: Vec<u8>,
+    spill: Option<(SpillFile, usize)>,
 }
 
 impl AssignmentSet {
 
     pub fn new() -> AssignmentSet {
-        AssignmentSet { assignment : Vec::new() }
+        AssignmentSet { assignment : Vec::new(), spill: None }
+    }
+
+    ///Assignment set which moves its values to a file in the spill directory
+    pub fn with_spill(config: &SpillConfig) -> AssignmentSet {
+        AssignmentSet { assignment: Vec::new(), spill: Some((SpillFile::new(&config.directory), config.threshold)) }
     }
 
     pub fn get(&self, id: MarkingId) -> Value {
-        if id < self.assignment.len() {
-            self.assignment[id].clone()
-        } else {
-            Value::Unknown
+        match (self.byte(id / 4) >> (2 * (id % 4))) & 3 {
+            1 => Value::Zero,
+            2 => Value::One,
+            _ => Value::Unknown,
         }
     }
 
     pub fn set(&mut self, id: MarkingId, value: Value) {
-        if id >= self.assignment.len() {
-            self.assignment.resize(id + 1, Value::Unknown);
+        let code = match value {
+            Value::Unknown => 0,
+            Value::Zero => 1,
+            Value::One => 2,
+        };
+        let shift = 2 * (id % 4);
+        let (index, spilled) = (id / 4, self.spilled());
+        if index < spilled {
+            let byte = (self.byte(index) & !(3 << shift)) | (code << shift);
+            self.spill.as_mut().unwrap().0.write_at(index, &[byte]);
+            return;
+        }
+        if index - spilled >= self.assignment.len() {
+            self.assignment.resize(index - spilled + 1, 0);
+        }
+        {
+            let ref mut byte = self.assignment[index - spilled];
+            *byte = (*byte & !(3 << shift)) | (code << shift);
+        }
+        if let Some((ref mut file, threshold)) = self.spill {
+            //values stay in memory if they can't be written
+            if self.assignment.len() > threshold && file.append(&self.assignment) {
+                self.assignment.clear();
+            }
+        }
+    }
+
+    ///True once reading or writing the spill file failed, values read since then are wrong
+    pub fn io_failed(&self) -> bool {
+        self.spill.as_ref().map(|spill| spill.0.failed()).unwrap_or(false)
+    }
+
+    //number of bytes moved to the spill file
+    fn spilled(&self) -> usize {
+        self.spill.as_ref().map(|spill| spill.0.bytes).unwrap_or(0)
+    }
+
+    fn byte(&self, index: usize) -> u8 {
+        let spilled = self.spilled();
+        if index < spilled {
+            let mut byte = [0];
+            self.spill.as_ref().unwrap().0.read_at(index, &mut byte);
+            byte[0]
+        } else {
+            self.assignment.get(index - spilled).cloned().unwrap_or(0)
         }
-        self.assignment[id] = value;
+    }
+
+    ///Memory taken by the values in bytes
+    pub fn memory(&self) -> usize {
+        self.assignment.capacity()
     }
 }
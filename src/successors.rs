@@ -1,6 +1,9 @@
 use graph::Graph;
 use storage::MarkingId;
-use petri_net::Marking;
+use petri_net::{Marking, PetriNet};
+use query::Query;
+use graph::Strategy;
+use frontier::Random;
 
 ///A successor iterator. Note that the iterator doesn't have access to the marking or even the
 ///marking id of the source. This is mainly to simplify the API.
@@ -14,11 +17,15 @@ pub trait Successors {
     fn pop(&mut self, source_id: MarkingId, graph: &mut Graph, cache: &mut Marking) -> Option<MarkingId>;
     ///Repeat the last encountered successor marking
     fn repeat_last(&mut self);
+    ///Whether the iterator needs Graph::stubborn, the graph only computes it in that case
+    fn uses_stubborn_sets() -> bool {
+        false
+    }
 }
 
 ///fire specific transition if possible and save result in dest. If transition is not valid,
 ///return false and contents of dest are undefined.
-fn fire_transition(dest: &mut Marking, source: &Marking, transition: &(Vec<(usize, u32)>, Vec<(usize, u32)>)) -> bool {
+pub fn fire_transition(dest: &mut Marking, source: &Marking, transition: &(Vec<(usize, u32)>, Vec<(usize, u32)>)) -> bool {
     let mut valid = true;
     dest.copy_from_slice(source);
     for &(place, value) in &transition.0 {
@@ -52,16 +59,12 @@ impl Successors for OTFSuccessors {
     }
 
     fn pop(&mut self, source_id: MarkingId, graph: &mut Graph, cache: &mut Marking) -> Option<MarkingId> {
+        graph.markings.get_into(source_id, &mut graph.decoded);
         while self.next_transition < graph.net.matrix.len() {
-            let fired;
-            {
-                let ref transition = graph.net.matrix[self.next_transition];
-                let ref source = graph.markings.get(source_id);
-                fired = fire_transition(cache, source, transition);
-            } //end source borrow so that we can access the graph
+            let transition = self.next_transition;
             self.next_transition += 1;
-            if fired {
-                let id = graph.markings.insert(&cache);
+            if fire_transition(cache, &graph.decoded, &graph.net.matrix[transition]) {
+                let id = graph.markings.insert_successor(&cache, source_id, transition);
                 return Some(id);
             }
         }
@@ -88,16 +91,11 @@ impl Successors for CachedSuccessors {
             self.next_index += 1;
             return Some(id);
         } else {
+            graph.markings.get_into(source_id, &mut graph.decoded);
             let mut next_transition = graph.cache.pop_transition(source_id);
             while next_transition < graph.net.matrix.len() {
-                let fired;
-                {
-                    let ref transition = graph.net.matrix[next_transition];
-                    let ref source = graph.markings.get(source_id);
-                    fired = fire_transition(cache, source, transition)
-                } //end source borrow so that we can access the graph
-                if fired {
-                    let id = graph.markings.insert(cache);
+                if fire_transition(cache, &graph.decoded, &graph.net.matrix[next_transition]) {
+                    let id = graph.markings.insert_successor(cache, source_id, next_transition);
                     graph.cache.push_successor(source_id, id);
                     self.next_index += 1;
                     return Some(id);
@@ -108,3 +106,177 @@ impl Successors for CachedSuccessors {
         None
     }
 }
+
+///Structural information needed to compute stubborn sets for a reachability query
+pub struct StubbornContext {
+    //transitions consuming tokens from each place
+    consumers: Vec<Vec<usize>>,
+    //transitions increasing the number of tokens in each place
+    producers: Vec<Vec<usize>>,
+    //transitions that can change the value of some atomic proposition of the query
+    interesting: Vec<usize>,
+}
+
+impl StubbornContext {
+
+    ///Stubborn sets are only used for (possibly negated) EF/AG of a state formula,
+    ///returns None for other queries.
+    pub fn new(net: &PetriNet, query: &Query) -> Option<StubbornContext> {
+        if !query.is_reachability() {
+            return None;
+        }
+        let places = net.initial_marking.len();
+        let mut visible = vec![false; places];
+        for &p in &query.support().places {
+            visible[p] = true;
+        }
+        let mut consumers = vec![Vec::new(); places];
+        let mut producers = vec![Vec::new(); places];
+        let mut interesting = Vec::new();
+        for (t, &(ref pre, ref post)) in net.matrix.iter().enumerate() {
+            let mut visible_change = false;
+            //only places on the arcs of t, a place on several arcs is seen more than once
+            for &(p, _) in pre.iter().chain(post) {
+                let consumed: u32 = pre.iter().filter(|a| a.0 == p).map(|a| a.1).sum();
+                let produced: u32 = post.iter().filter(|a| a.0 == p).map(|a| a.1).sum();
+                if consumed > 0 && consumers[p].last() != Some(&t) {
+                    consumers[p].push(t);
+                }
+                if produced > consumed && producers[p].last() != Some(&t) {
+                    producers[p].push(t);
+                }
+                if produced != consumed && visible[p] {
+                    visible_change = true;
+                }
+            }
+            if visible_change {
+                interesting.push(t);
+            }
+        }
+        Some(StubbornContext { consumers: consumers, producers: producers, interesting: interesting })
+    }
+
+    ///Compute enabled transitions of a stubborn set in given marking. Every path to a marking
+    ///with different value of the query atoms has to fire an interesting transition, so starting
+    ///from those and closing the set under conflicts (enabled transitions) and necessary enabling
+    ///transitions (disabled ones) preserves reachability of such markings.
+    fn enabled_stubborn(&self, net: &PetriNet, marking: &Marking) -> Vec<usize> {
+        let mut in_set = vec![false; net.matrix.len()];
+        let mut work = Vec::new();
+        for &t in &self.interesting {
+            in_set[t] = true;
+            work.push(t);
+        }
+        let mut enabled = Vec::new();
+        while let Some(t) = work.pop() {
+            let ref pre = net.matrix[t].0;
+            let scapegoat = pre.iter().find(|&&(p, w)| marking[p] < w).map(|&(p, _)| p);
+            let added: &Vec<usize> = if let Some(p) = scapegoat {
+                &self.producers[p]
+            } else {
+                enabled.push(t);
+                for &(p, _) in pre {
+                    for &c in &self.consumers[p] {
+                        if !in_set[c] {
+                            in_set[c] = true;
+                            work.push(c);
+                        }
+                    }
+                }
+                continue;
+            };
+            for &c in added {
+                if !in_set[c] {
+                    in_set[c] = true;
+                    work.push(c);
+                }
+            }
+        }
+        enabled.sort();
+        enabled
+    }
+}
+
+///Partial order reduction: only fires enabled transitions of a stubborn set. Falls back to all
+///enabled transitions when the graph has no stubborn context (query isn't a reachability query).
+pub struct StubbornSuccessors {
+    transitions: Option<Vec<usize>>,
+    next_index: usize,
+}
+
+impl Successors for StubbornSuccessors {
+
+    fn new() -> StubbornSuccessors {
+        StubbornSuccessors { transitions: None, next_index: 0 }
+    }
+
+    fn repeat_last(&mut self) {
+        self.next_index = self.next_index.checked_sub(1).unwrap();
+    }
+
+    fn uses_stubborn_sets() -> bool {
+        true
+    }
+
+    fn pop(&mut self, source_id: MarkingId, graph: &mut Graph, cache: &mut Marking) -> Option<MarkingId> {
+        graph.markings.get_into(source_id, &mut graph.decoded);
+        if self.transitions.is_none() {
+            self.transitions = Some(match graph.stubborn {
+                Some(ref context) => context.enabled_stubborn(graph.net, &graph.decoded),
+                None => (0..graph.net.matrix.len()).collect(),
+            });
+        }
+        let transitions = self.transitions.as_ref().unwrap();
+        while self.next_index < transitions.len() {
+            let transition = transitions[self.next_index];
+            self.next_index += 1;
+            if fire_transition(cache, &graph.decoded, &graph.net.matrix[transition]) {
+                return Some(graph.markings.insert_successor(cache, source_id, transition));
+            }
+        }
+        None
+    }
+}
+
+///Returns successors of the wrapped iterator in random order. The order is given by the seed of
+///RandomDepthFirst strategy and the marking, so a marking always gets the same order.
+pub struct ShuffledSuccessors<S: Successors> {
+    inner: S,
+    successors: Option<Vec<MarkingId>>,
+    next_index: usize,
+}
+
+impl <S: Successors> Successors for ShuffledSuccessors<S> {
+
+    fn new() -> ShuffledSuccessors<S> {
+        ShuffledSuccessors { inner: S::new(), successors: None, next_index: 0 }
+    }
+
+    fn repeat_last(&mut self) {
+        self.next_index = self.next_index.checked_sub(1).unwrap();
+    }
+
+    fn uses_stubborn_sets() -> bool {
+        S::uses_stubborn_sets()
+    }
+
+    fn pop(&mut self, source_id: MarkingId, graph: &mut Graph, cache: &mut Marking) -> Option<MarkingId> {
+        if self.successors.is_none() {
+            let mut successors = Vec::new();
+            while let Some(id) = self.inner.pop(source_id, graph, cache) {
+                successors.push(id);
+            }
+            let seed = match graph.strategy {
+                Strategy::RandomDepthFirst(seed) => seed,
+                _ => 0,
+            };
+            Random::new(seed ^ (source_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)).shuffle(&mut successors);
+            self.successors = Some(successors);
+        }
+        let successor = self.successors.as_ref().unwrap().get(self.next_index).cloned();
+        if successor.is_some() {
+            self.next_index += 1;
+        }
+        successor
+    }
+}
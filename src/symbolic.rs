@@ -0,0 +1,613 @@
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use petri_net::*;
+use query::*;
+use query::Operator::*;
+
+//Note: Markings are stored in a quasi-reduced multi-valued decision diagram. There is one level
+//per place (place i is at level i) and every edge skips exactly one level, so a node at level i
+//maps possible token counts of place i to nodes at level i + 1. Terminals are at level "places".
+//Edges leading to the empty set are not stored at all.
+
+pub type NodeId = usize;
+
+///The empty set of markings
+pub const FALSE: NodeId = 0;
+///The set containing the only (empty) suffix of a marking
+pub const TRUE: NodeId = 1;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Node {
+    level: usize,
+    edges: Vec<(u32, NodeId)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Operation {
+    Union, Intersection, Difference,
+}
+
+///Decision diagram package. Nodes are never freed, so ids stay valid for the whole lifetime.
+pub struct Mdd {
+    levels: usize,
+    nodes: Vec<Node>,
+    unique: HashMap<Node, NodeId>,
+    cache: HashMap<(Operation, NodeId, NodeId), NodeId>,
+}
+
+impl Mdd {
+
+    pub fn new(levels: usize) -> Mdd {
+        let terminal = Node { level: levels, edges: vec![] };
+        Mdd {
+            levels: levels,
+            nodes: vec![terminal.clone(), terminal],
+            unique: HashMap::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    ///Number of allocated nodes (including terminals)
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn level(&self, node: NodeId) -> usize {
+        self.nodes[node].level
+    }
+
+    pub fn edges(&self, node: NodeId) -> &Vec<(u32, NodeId)> {
+        &self.nodes[node].edges
+    }
+
+    ///Find or create node with given edges (edges have to be sorted by value)
+    pub fn make(&mut self, level: usize, edges: Vec<(u32, NodeId)>) -> NodeId {
+        let edges: Vec<(u32, NodeId)> = edges.into_iter().filter(|&(_, child)| child != FALSE).collect();
+        if edges.is_empty() {
+            return FALSE;
+        }
+        let node = Node { level: level, edges: edges };
+        if let Some(&id) = self.unique.get(&node) {
+            return id;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(node.clone());
+        self.unique.insert(node, id);
+        id
+    }
+
+    ///Set containing exactly one marking
+    pub fn singleton(&mut self, marking: &Marking) -> NodeId {
+        let mut node = TRUE;
+        for level in (0..self.levels).rev() {
+            node = self.make(level, vec![(marking[level], node)]);
+        }
+        node
+    }
+
+    pub fn contains(&self, set: NodeId, marking: &Marking) -> bool {
+        let mut node = set;
+        for level in 0..self.levels {
+            match self.nodes[node].edges.iter().find(|e| e.0 == marking[level]) {
+                Some(&(_, child)) => node = child,
+                None => return false,
+            }
+        }
+        node == TRUE
+    }
+
+    ///Number of markings in the set (saturates at usize::MAX)
+    pub fn count(&self, set: NodeId) -> usize {
+        let mut counts: HashMap<NodeId, usize> = HashMap::new();
+        self.count_inner(set, &mut counts)
+    }
+
+    fn count_inner(&self, node: NodeId, counts: &mut HashMap<NodeId, usize>) -> usize {
+        if node == FALSE || node == TRUE {
+            return node;
+        }
+        if let Some(&count) = counts.get(&node) {
+            return count;
+        }
+        let count = self.nodes[node].edges.iter()
+            .fold(0usize, |acc, &(_, child)| acc.saturating_add(self.count_inner(child, counts)));
+        counts.insert(node, count);
+        count
+    }
+
+    pub fn union(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        if a == b || b == FALSE { return a; }
+        if a == FALSE { return b; }
+        self.apply(Operation::Union, a, b)
+    }
+
+    pub fn intersection(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        if a == b || b == FALSE { return b; }
+        if a == FALSE { return a; }
+        self.apply(Operation::Intersection, a, b)
+    }
+
+    pub fn difference(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        if a == b || a == FALSE { return FALSE; }
+        if b == FALSE { return a; }
+        self.apply(Operation::Difference, a, b)
+    }
+
+    //both nodes are non-empty and at the same level, so they are terminals only if both are TRUE,
+    //which is handled by the a == b cases above
+    fn apply(&mut self, operation: Operation, a: NodeId, b: NodeId) -> NodeId {
+        let key = match operation {
+            Operation::Difference => (operation, a, b),
+            _ => (operation, a.min(b), a.max(b)),
+        };
+        if let Some(&result) = self.cache.get(&key) {
+            return result;
+        }
+        let level = self.nodes[a].level;
+        let left = self.nodes[a].edges.clone();
+        let right = self.nodes[b].edges.clone();
+        let mut edges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < left.len() || j < right.len() {
+            let (value, l, r) = if j == right.len() || (i < left.len() && left[i].0 < right[j].0) {
+                i += 1;
+                (left[i - 1].0, left[i - 1].1, FALSE)
+            } else if i == left.len() || right[j].0 < left[i].0 {
+                j += 1;
+                (right[j - 1].0, FALSE, right[j - 1].1)
+            } else {
+                i += 1; j += 1;
+                (left[i - 1].0, left[i - 1].1, right[j - 1].1)
+            };
+            let child = match operation {
+                Operation::Union => self.union(l, r),
+                Operation::Intersection => self.intersection(l, r),
+                Operation::Difference => self.difference(l, r),
+            };
+            edges.push((value, child));
+        }
+        let result = self.make(level, edges);
+        self.cache.insert(key, result);
+        result
+    }
+}
+
+//Effect of one transition on each place, ordered by level
+struct Relation {
+    //(place, consumed, produced)
+    arcs: Vec<(usize, u32, u32)>,
+}
+
+impl Relation {
+
+    fn new(transition: &(Vec<(usize, u32)>, Vec<(usize, u32)>)) -> Relation {
+        let mut effect: HashMap<usize, (u32, u32)> = HashMap::new();
+        for &(p, w) in &transition.0 {
+            effect.entry(p).or_insert((0, 0)).0 += w;
+        }
+        for &(p, w) in &transition.1 {
+            effect.entry(p).or_insert((0, 0)).1 += w;
+        }
+        let mut arcs: Vec<(usize, u32, u32)> = effect.into_iter().map(|(p, (pre, post))| (p, pre, post)).collect();
+        arcs.sort();
+        Relation { arcs: arcs }
+    }
+}
+
+///How the set of reachable markings is generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exploration {
+    ///Fire all transitions on the newly discovered markings until nothing new is found
+    BreadthFirst,
+    ///Exploit locality of transitions: every node of the diagram is closed under transitions
+    ///that only touch its level and the levels below before it is used higher up
+    Saturation,
+}
+
+///Properties of the reachable state space
+#[derive(Debug, Clone)]
+pub struct Statistics {
+    pub markings: usize,
+    ///Nodes of the diagram representing the reachable markings
+    pub nodes: usize,
+    ///Maximal number of tokens in each place
+    pub place_bounds: Vec<u32>,
+    ///Reachable markings without successors
+    pub deadlocks: usize,
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Markings: {}", self.markings)?;
+        writeln!(f, "Diagram nodes: {}", self.nodes)?;
+        writeln!(f, "Max tokens in place: {}", self.place_bounds.iter().cloned().max().unwrap_or(0))?;
+        write!(f, "Deadlocks: {}", self.deadlocks)
+    }
+}
+
+///Symbolic checker: the reachable markings are computed in a decision diagram
+///and every subquery is then computed as a set of markings using pre-images.
+pub struct SymbolicChecker<'a> {
+    pub net: &'a PetriNet,
+    pub mdd: Mdd,
+    ///Set of all reachable markings
+    pub reachable: NodeId,
+    relations: Vec<Relation>,
+    //transitions by the topmost level they touch
+    events: Vec<Vec<usize>>,
+    saturated: HashMap<NodeId, NodeId>,
+    saturation_cache: HashMap<(NodeId, usize, usize), NodeId>,
+    image_cache: HashMap<(NodeId, usize, usize), NodeId>,
+    pre_image_cache: HashMap<(NodeId, usize, usize), NodeId>,
+}
+
+impl <'a> SymbolicChecker<'a> {
+
+    ///Compute reachable markings using given exploration method
+    pub fn new(net: &'a PetriNet, exploration: Exploration) -> SymbolicChecker<'a> {
+        let relations: Vec<Relation> = net.matrix.iter().map(Relation::new).collect();
+        let mut events = vec![Vec::new(); net.initial_marking.len()];
+        for (t, relation) in relations.iter().enumerate() {
+            //transitions without arcs don't change anything
+            if let Some(&(top, _, _)) = relation.arcs.first() {
+                events[top].push(t);
+            }
+        }
+        let mut checker = SymbolicChecker {
+            net: net,
+            mdd: Mdd::new(net.initial_marking.len()),
+            reachable: FALSE,
+            relations: relations,
+            events: events,
+            saturated: HashMap::new(),
+            saturation_cache: HashMap::new(),
+            image_cache: HashMap::new(),
+            pre_image_cache: HashMap::new(),
+        };
+        let initial = checker.mdd.singleton(&net.initial_marking);
+        checker.reachable = match exploration {
+            Exploration::BreadthFirst => checker.breadth_first(initial),
+            Exploration::Saturation => checker.saturate(initial),
+        };
+        checker
+    }
+
+    fn breadth_first(&mut self, initial: NodeId) -> NodeId {
+        let mut reachable = initial;
+        let mut frontier = initial;
+        while frontier != FALSE {
+            let mut next = FALSE;
+            for t in 0..self.relations.len() {
+                let image = self.image(frontier, t);
+                next = self.mdd.union(next, image);
+            }
+            frontier = self.mdd.difference(next, reachable);
+            reachable = self.mdd.union(reachable, frontier);
+        }
+        reachable
+    }
+
+    ///Close the node under all transitions that start at its level or below. Children are
+    ///saturated first, then transitions of this level are fired until a fixed point is reached.
+    fn saturate(&mut self, node: NodeId) -> NodeId {
+        if node == FALSE || node == TRUE {
+            return node;
+        }
+        if let Some(&result) = self.saturated.get(&node) {
+            return result;
+        }
+        let level = self.mdd.level(node);
+        let mut edges = Vec::new();
+        for (value, child) in self.mdd.edges(node).clone() {
+            edges.push((value, self.saturate(child)));
+        }
+        let mut result = self.mdd.make(level, edges);
+        let events = self.events[level].clone();
+        loop {
+            let start = result;
+            for &t in &events {
+                let image = self.fire_saturated(result, t, 0);
+                result = self.mdd.union(result, image);
+            }
+            if result == start {
+                break;
+            }
+        }
+        self.saturated.insert(node, result);
+        self.saturated.insert(result, result);
+        result
+    }
+
+    ///Image of a saturated node under transition t. Union of saturated sets is saturated, so only
+    ///the nodes created below the top level of the transition have to be saturated again.
+    fn fire_saturated(&mut self, node: NodeId, t: usize, arc: usize) -> NodeId {
+        if node == FALSE || arc == self.relations[t].arcs.len() {
+            return node;
+        }
+        let key = (node, t, arc);
+        if let Some(&result) = self.saturation_cache.get(&key) {
+            return result;
+        }
+        let level = self.mdd.level(node);
+        let (place, consumed, produced) = self.relations[t].arcs[arc];
+        let mut edges = Vec::new();
+        for (value, child) in self.mdd.edges(node).clone() {
+            if place != level {
+                edges.push((value, self.fire_saturated(child, t, arc)));
+            } else if value >= consumed {
+                edges.push((value - consumed + produced, self.fire_saturated(child, t, arc + 1)));
+            }
+        }
+        let mut result = self.mdd.make(level, edges);
+        if arc > 0 || place != level {
+            result = self.saturate(result);
+        }
+        self.saturation_cache.insert(key, result);
+        result
+    }
+
+    ///Number of reachable markings
+    pub fn len(&self) -> usize {
+        self.mdd.count(self.reachable)
+    }
+
+    ///Maximal value of the expression over all reachable markings (i.e. place-bound of the
+    ///places in a tokens-count expression), every node gets the best value of its suffixes
+    pub fn upper_bound(&self, expression: &Expression) -> i64 {
+        let mut weights = vec![0; self.net.initial_marking.len()];
+        for &(place, c) in &expression.coefficients {
+            weights[place] += c;
+        }
+        let mut best = HashMap::new();
+        expression.constant + self.max_suffix(self.reachable, &weights, &mut best)
+    }
+
+    fn max_suffix(&self, node: NodeId, weights: &Vec<i64>, best: &mut HashMap<NodeId, i64>) -> i64 {
+        if node == TRUE {
+            return 0;
+        }
+        if let Some(&value) = best.get(&node) {
+            return value;
+        }
+        let level = self.mdd.level(node);
+        //only terminals are without edges
+        let value = self.mdd.edges(node).iter().map(|&(tokens, child)| {
+            weights[level] * tokens as i64 + self.max_suffix(child, weights, best)
+        }).max().unwrap();
+        best.insert(node, value);
+        value
+    }
+
+    ///Check the query in the initial marking
+    pub fn check(&mut self, query: &Query) -> bool {
+        //reachability only needs the reachable set, no fixed point is necessary
+        match query.operator {
+            EF(ref inner) if inner.is_state_formula() => return self.evaluate(inner) != FALSE,
+            AG(ref inner) if inner.is_state_formula() => {
+                let inner = self.evaluate(inner);
+                return self.mdd.difference(self.reachable, inner) == FALSE;
+            }
+            _ => {}
+        }
+        let result = self.evaluate(query);
+        self.mdd.contains(result, &self.net.initial_marking)
+    }
+
+    pub fn statistics(&mut self) -> Statistics {
+        let mut place_bounds = vec![0; self.net.initial_marking.len()];
+        let mut visited = HashSet::new();
+        let mut stack = vec![self.reachable];
+        while let Some(node) = stack.pop() {
+            if node == FALSE || node == TRUE || !visited.insert(node) {
+                continue;
+            }
+            let level = self.mdd.level(node);
+            for &(value, child) in self.mdd.edges(node) {
+                place_bounds[level] = max(place_bounds[level], value);
+                stack.push(child);
+            }
+        }
+        let reachable = self.reachable;
+        let alive = self.exists_next(reachable);
+        let deadlocks = self.mdd.difference(reachable, alive);
+        Statistics {
+            markings: self.len(),
+            nodes: visited.len() + 2,
+            place_bounds: place_bounds,
+            deadlocks: self.mdd.count(deadlocks),
+        }
+    }
+
+    ///Compute the set of reachable markings that satisfy the query
+    pub fn evaluate(&mut self, query: &Query) -> NodeId {
+        match query.operator {
+            Atom(ref proposition) => self.atom(proposition),
+            Not(ref inner) => {
+                let inner = self.evaluate(inner);
+                self.mdd.difference(self.reachable, inner)
+            }
+            And(ref items) => {
+                let mut result = self.reachable;
+                for item in items {
+                    let item = self.evaluate(item);
+                    result = self.mdd.intersection(result, item);
+                }
+                result
+            }
+            Or(ref items) => {
+                let mut result = FALSE;
+                for item in items {
+                    let item = self.evaluate(item);
+                    result = self.mdd.union(result, item);
+                }
+                result
+            }
+            EX(ref inner) => {
+                let inner = self.evaluate(inner);
+                self.exists_next(inner)
+            }
+            AX(ref inner) => {
+                let inner = self.evaluate(inner);
+                self.all_next(inner)
+            }
+            EF(ref inner) => {
+                let reach = self.evaluate(inner);
+                self.exists_until(self.reachable, reach)
+            }
+            EU(ref path, ref reach) => {
+                let path = self.evaluate(path);
+                let reach = self.evaluate(reach);
+                self.exists_until(path, reach)
+            }
+            AF(ref inner) => {
+                let reach = self.evaluate(inner);
+                self.all_until(self.reachable, reach)
+            }
+            AU(ref path, ref reach) => {
+                let path = self.evaluate(path);
+                let reach = self.evaluate(reach);
+                self.all_until(path, reach)
+            }
+            //greatest fixed points are computed as complements of their duals
+            AG(ref inner) => {
+                let inner = self.evaluate(inner);
+                let reach = self.mdd.difference(self.reachable, inner);
+                let dual = self.exists_until(self.reachable, reach);
+                self.mdd.difference(self.reachable, dual)
+            }
+            EG(ref inner) => {
+                let inner = self.evaluate(inner);
+                let reach = self.mdd.difference(self.reachable, inner);
+                let dual = self.all_until(self.reachable, reach);
+                self.mdd.difference(self.reachable, dual)
+            }
+        }
+    }
+
+    ///Atomic propositions are only given as predicates, but they read just a few places. We walk
+    ///the reachable set and evaluate the predicate once the last of those places is assigned.
+    fn atom(&mut self, proposition: &Proposition) -> NodeId {
+        let mut places = proposition.places.clone();
+        places.sort();
+        places.dedup();
+        let mut marking = vec![0; self.net.initial_marking.len()];
+        let mut memo = HashMap::new();
+        let reachable = self.reachable;
+        self.filter(reachable, proposition, &places, &mut marking, &mut memo)
+    }
+
+    fn filter(
+        &mut self, node: NodeId, proposition: &Proposition, places: &Vec<usize>,
+        marking: &mut Marking, memo: &mut HashMap<(NodeId, Vec<u32>), NodeId>
+    ) -> NodeId {
+        let level = self.mdd.level(node);
+        if node == FALSE || places.last().map(|&p| p < level).unwrap_or(true) {
+            return if proposition.eval(marking) { node } else { FALSE };
+        }
+        let key = (node, places.iter().filter(|&&p| p < level).map(|&p| marking[p]).collect());
+        if let Some(&result) = memo.get(&key) {
+            return result;
+        }
+        let mut edges = Vec::new();
+        for (value, child) in self.mdd.edges(node).clone() {
+            marking[level] = value;
+            edges.push((value, self.filter(child, proposition, places, marking, memo)));
+        }
+        marking[level] = 0;
+        let result = self.mdd.make(level, edges);
+        memo.insert(key, result);
+        result
+    }
+
+    ///Reachable markings with at least one successor in the set
+    fn exists_next(&mut self, set: NodeId) -> NodeId {
+        let mut result = FALSE;
+        for t in 0..self.relations.len() {
+            let pre = self.pre_image(set, t);
+            result = self.mdd.union(result, pre);
+        }
+        self.mdd.intersection(self.reachable, result)
+    }
+
+    ///Reachable markings with all successors in the set (markings without successors included)
+    fn all_next(&mut self, set: NodeId) -> NodeId {
+        let outside = self.mdd.difference(self.reachable, set);
+        let some_outside = self.exists_next(outside);
+        self.mdd.difference(self.reachable, some_outside)
+    }
+
+    fn exists_until(&mut self, path: NodeId, reach: NodeId) -> NodeId {
+        let mut result = reach;
+        loop {
+            let next = self.exists_next(result);
+            let step = self.mdd.intersection(path, next);
+            let new_result = self.mdd.union(result, step);
+            if new_result == result {
+                return result;
+            }
+            result = new_result;
+        }
+    }
+
+    ///Path has to be maximal, so markings without successors only satisfy reach
+    fn all_until(&mut self, path: NodeId, reach: NodeId) -> NodeId {
+        let has_successor = self.exists_next(self.reachable);
+        let path = self.mdd.intersection(path, has_successor);
+        let mut result = reach;
+        loop {
+            let next = self.all_next(result);
+            let step = self.mdd.intersection(path, next);
+            let new_result = self.mdd.union(result, step);
+            if new_result == result {
+                return result;
+            }
+            result = new_result;
+        }
+    }
+
+    ///Markings reached by firing transition t in some marking of the set
+    fn image(&mut self, set: NodeId, t: usize) -> NodeId {
+        self.apply_relation(set, t, 0, true)
+    }
+
+    ///Markings in which transition t is enabled and leads to the set
+    fn pre_image(&mut self, set: NodeId, t: usize) -> NodeId {
+        self.apply_relation(set, t, 0, false)
+    }
+
+    //arc is the index of the first arc of the relation at this level or below
+    fn apply_relation(&mut self, node: NodeId, t: usize, arc: usize, forward: bool) -> NodeId {
+        if node == FALSE || arc == self.relations[t].arcs.len() {
+            return node;
+        }
+        let key = (node, t, arc);
+        let cached = if forward { self.image_cache.get(&key) } else { self.pre_image_cache.get(&key) };
+        if let Some(&result) = cached {
+            return result;
+        }
+        let level = self.mdd.level(node);
+        let (place, consumed, produced) = self.relations[t].arcs[arc];
+        let (required, remove, add) = if forward {
+            (consumed, consumed, produced)
+        } else {
+            (produced, produced, consumed)
+        };
+        let mut edges = Vec::new();
+        for (value, child) in self.mdd.edges(node).clone() {
+            if place != level {
+                edges.push((value, self.apply_relation(child, t, arc, forward)));
+            } else if value >= required {
+                edges.push((value - remove + add, self.apply_relation(child, t, arc + 1, forward)));
+            }
+        }
+        let result = self.mdd.make(level, edges);
+        if forward {
+            self.image_cache.insert(key, result);
+        } else {
+            self.pre_image_cache.insert(key, result);
+        }
+        result
+    }
+}
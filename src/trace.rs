@@ -0,0 +1,149 @@
+use std::fmt;
+use std::collections::HashMap;
+
+use query::*;
+use query::Operator::*;
+use graph::Graph;
+use storage::MarkingId;
+use successors::*;
+
+///A firing sequence starting in the initial marking. If loop_start is set, the suffix of
+///steps starting at that index can be repeated forever (lasso), otherwise the trace ends
+///in a marking that satisfies the target or in a deadlock.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub loop_start: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub transition: String,
+    pub marking: MarkingId,
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.steps.is_empty() {
+            return write!(f, "<initial marking>");
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            if self.loop_start == Some(i) {
+                write!(f, "(")?;
+            }
+            write!(f, "{}", step.transition)?;
+        }
+        if self.loop_start.is_some() {
+            write!(f, ")*")?;
+        }
+        Ok(())
+    }
+}
+
+///Build a witness (if the query holds) or a counterexample (if it doesn't) for the top level
+///temporal operator of the query. Has to be called right after Graph::search with record_traces
+///enabled, so that the parent pointers recorded by the search can be followed.
+///Returns None if the result can't be explained by a single path (i.e. AF is true).
+pub fn explain<S: Successors>(graph: &mut Graph, query: &Query) -> Option<Trace> {
+    let root = graph.markings.insert(&graph.net.initial_marking);
+    let value = graph.check::<S>(root, query);
+    explain_inner::<S>(graph, root, query, value)
+}
+
+fn explain_inner<S: Successors>(graph: &mut Graph, root: MarkingId, query: &Query, value: bool) -> Option<Trace> {
+    match (&query.operator, value) {
+        (&Not(ref inner), _) => explain_inner::<S>(graph, root, inner, !value),
+        (&EX(ref inner), true) | (&AX(ref inner), false) => {
+            let mut succ = S::new();
+            let mut cache = graph.net.initial_marking.clone();
+            while let Some(id) = succ.pop(root, graph, &mut cache) {
+                if graph.check::<S>(id, inner) == value {
+                    return trace_from(graph, vec![root, id], None);
+                }
+            }
+            None
+        }
+        (&EF(ref target), true) | (&EU(_, ref target), true) => recorded_path::<S>(graph, root, target, true),
+        (&AG(ref target), false) => recorded_path::<S>(graph, root, target, false),
+        (&EG(_), true) | (&AF(_), false) => lasso::<S>(graph, root, query, None, value),
+        (&AU(ref path, _), false) => lasso::<S>(graph, root, query, Some(path), value),
+        _ => None,
+    }
+}
+
+///Turn a sequence of markings into steps, finding the transition that connects each pair
+fn trace_from(graph: &Graph, markings: Vec<MarkingId>, loop_start: Option<usize>) -> Option<Trace> {
+    let net = graph.net;
+    let names = net.transition_names();
+    let mut cache = net.initial_marking.clone();
+    let mut steps = Vec::with_capacity(markings.len());
+    for pair in markings.windows(2) {
+        let (source, target) = (graph.markings.get(pair[0]), graph.markings.get(pair[1]));
+        let transition = (0..net.matrix.len()).find(|&t| {
+            fire_transition(&mut cache, &source, &net.matrix[t]) && cache == target
+        })?;
+        steps.push(Step { transition: names[transition].to_string(), marking: pair[1] });
+    }
+    Some(Trace { steps: steps, loop_start: loop_start })
+}
+
+///Follow the parent pointers recorded by the search back from the marking that decided the query.
+///The target is checked again, in case the search stopped at a marking decided by another query.
+fn recorded_path<S: Successors>(graph: &mut Graph, root: MarkingId, target: &Query, expected: bool) -> Option<Trace> {
+    let end = graph.trace_end?;
+    if graph.check::<S>(end, target) != expected {
+        return None;
+    }
+    let mut markings = vec![end];
+    let mut current = end;
+    while current != root {
+        current = *graph.parents.get(&current)?;
+        markings.push(current);
+        if markings.len() > graph.parents.len() + 1 {
+            return None;    //parent pointers of different searches form a cycle
+        }
+    }
+    markings.reverse();
+    trace_from(graph, markings, None)
+}
+
+///Follow markings where the EG/AF/AU query has given value until a marking repeats, a deadlock is
+///reached or (for AU) the path formula stops holding. Such marking always has a successor with the
+///same value otherwise, so no backtracking is needed.
+fn lasso<S: Successors>(graph: &mut Graph, root: MarkingId, query: &Query, path: Option<&Query>, value: bool) -> Option<Trace> {
+    let mut markings = vec![root];
+    let mut position: HashMap<MarkingId, usize> = HashMap::new();
+    let mut current = root;
+    let mut cache = graph.net.initial_marking.clone();
+    position.insert(root, 0);
+    loop {
+        if let Some(path) = path {
+            if !graph.check::<S>(current, path) {
+                return trace_from(graph, markings, None);
+            }
+        }
+        let mut succ = S::new();
+        let mut next = None;
+        while let Some(id) = succ.pop(current, graph, &mut cache) {
+            if graph.check::<S>(id, query) == value {
+                next = Some(id);
+                break;
+            }
+        }
+        match next {
+            None => return trace_from(graph, markings, None),   //deadlock
+            Some(id) => {
+                if let Some(&start) = position.get(&id) {
+                    markings.push(id);
+                    return trace_from(graph, markings, Some(start));
+                }
+                markings.push(id);
+                position.insert(id, markings.len() - 1);
+                current = id;
+            }
+        }
+    }
+}